@@ -0,0 +1,121 @@
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use serde_json::{json, Map, Value};
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::{
+    database::audit::{query_events, NewEvent},
+    error::ApiError,
+    middleware::request_id::RequestId,
+    model::audit::{AuditAction, AuditPage, AuditQuery},
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// Who performs an action, and the request they perform it with.
+///
+/// Requests are `anonymous` until API keys are verified, as the `X-Api-Key`
+/// header alone would let callers choose who they are recorded as.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub name: String,
+    pub request_id: Option<String>,
+}
+
+impl Actor {
+    /// The operator of the administrative CLI.
+    pub fn cli() -> Self {
+        Self {
+            name: "cli".into(),
+            request_id: None,
+        }
+    }
+
+    /// The server itself, acting on its own schedule.
+    pub fn system() -> Self {
+        Self {
+            name: "system".into(),
+            request_id: None,
+        }
+    }
+
+    /// The audit event of an `action` by this actor, which the change it
+    /// records appends in its transaction.
+    pub fn event(&self, action: AuditAction, details: Map<String, Value>) -> NewEvent<'_> {
+        NewEvent {
+            action,
+            actor: &self.name,
+            request_id: self.request_id.as_deref(),
+            details,
+        }
+    }
+}
+
+impl FromRequest for Actor {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.as_str().to_owned());
+        ready(Ok(Self {
+            name: "anonymous".into(),
+            request_id,
+        }))
+    }
+}
+
+/// Details holding a single value.
+pub fn details(key: &str, value: impl Into<Value>) -> Map<String, Value> {
+    Map::from_iter([(key.to_owned(), value.into())])
+}
+
+/// The fields that differ between the record `before` and `after` a change,
+/// with both of their values, as record events hold them. Missing fields are null.
+pub fn changes(before: Option<&Value>, after: Option<&Value>) -> Map<String, Value> {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    let changes: Map<_, _> = before
+        .keys()
+        .chain(after.keys().filter(|key| !before.contains_key(*key)))
+        .filter(|key| *key != "id" && before.get(*key) != after.get(*key))
+        .map(|key| {
+            let change = json!({ "before": before.get(key), "after": after.get(key) });
+            (key.clone(), change)
+        })
+        .collect();
+    details("changes", changes)
+}
+
+/// Reads a page of the audit log of the database `db` is connected to.
+pub async fn query_page(db: &Surreal<Any>, query: &AuditQuery) -> Result<AuditPage, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::bad_request(format!(
+            "`limit` must be between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(ApiError::bad_request("`from` must be before `to`"));
+        }
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    // One more event than requested tells whether there is another page.
+    let mut events = query_events(db, query, offset, limit + 1).await?;
+    let next_offset = (events.len() > limit as usize).then(|| offset + limit);
+    events.truncate(limit as usize);
+    Ok(AuditPage {
+        events,
+        next_offset,
+    })
+}
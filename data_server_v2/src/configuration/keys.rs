@@ -0,0 +1,68 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_path_to_error::Segment;
+
+/// How many unknown keys are reported at most.
+const MAX_ERRORS: usize = 64;
+
+/// Lists the unknown keys of `config` as a `T`, then its first missing or
+/// invalid key, if any.
+///
+/// Deserialization stops at the first problem. Unknown keys are removed and the
+/// settings deserialized again, so that they are all reported together, while
+/// other problems are reported one at a time.
+pub(super) fn key_errors<T: DeserializeOwned>(mut config: Value) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    while errors.len() < MAX_ERRORS {
+        let error = match serde_path_to_error::deserialize::<_, T>(config.clone()) {
+            Ok(_) => break,
+            Err(error) => error,
+        };
+        let mut path: Vec<String> = error
+            .path()
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Map { key } => Some(key.clone()),
+                Segment::Seq { index } => Some(index.to_string()),
+                Segment::Enum { .. } | Segment::Unknown => None,
+            })
+            .collect();
+        let message = error.into_inner().to_string();
+
+        if let Some(field) = quoted_after(&message, "missing field ") {
+            path.push(field.into());
+            errors.push(format!("`{}` is missing", path.join(".")));
+        } else if message.starts_with("unknown field ") {
+            errors.push(format!("`{}` is not a known setting", path.join(".")));
+            if remove(&mut config, &path) {
+                continue;
+            }
+        } else {
+            errors.push(format!("`{}` is invalid: {message}", path.join(".")));
+        }
+        break;
+    }
+    errors
+}
+
+/// Takes `field` out of serde's "missing field `field`" messages.
+fn quoted_after<'a>(message: &'a str, prefix: &str) -> Option<&'a str> {
+    message.strip_prefix(prefix)?.split('`').nth(1)
+}
+
+fn remove(config: &mut Value, path: &[String]) -> bool {
+    let Some((key, parent)) = path.split_last() else {
+        return false;
+    };
+    let mut target = config;
+    for segment in parent {
+        match target.get_mut(segment.as_str()) {
+            Some(value) => target = value,
+            None => return false,
+        }
+    }
+    target
+        .as_object_mut()
+        .is_some_and(|object| object.remove(key.as_str()).is_some())
+}
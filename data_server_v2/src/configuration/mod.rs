@@ -1,42 +1,249 @@
 use config::{Config, ConfigError};
+use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
-use std::fmt::Display;
-use surrealdb::opt::auth::Root;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt::Display, path::PathBuf};
+use surrealdb::opt::auth::{Database, Namespace, Root};
 
-#[derive(Debug, Deserialize)]
+use tracing_subscriber::EnvFilter;
+
+use crate::database::{credentials::SecretCipher, ROOT_DATABASE, ROOT_NAMESPACE};
+
+mod keys;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub limits: LimitSettings,
+    pub usage: UsageSettings,
+    pub recycle_bin: RecycleBinSettings,
+    pub webhooks: WebhookSettings,
+    pub telemetry: TelemetrySettings,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
+    /// Serves `/metrics` on this port instead of the public one when set.
+    pub admin_port: Option<u16>,
+    /// Serves HTTPS instead of plain HTTP when set.
+    pub tls: Option<TlsSettings>,
+    /// How long in-flight requests may take to finish once shutdown begins.
+    pub shutdown_timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// The certificate chain presented when no domain certificate matches.
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    #[serde(default)]
+    pub min_version: TlsVersion,
+    /// Certificates selected by SNI, such as those of custom instance domains.
+    #[serde(default)]
+    pub certificates: Vec<DomainCertificate>,
+    /// How often certificate files are checked for changes.
+    pub reload_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DomainCertificate {
+    /// An exact domain, or a wildcard such as `*.example.com`.
+    pub domain: String,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+pub enum TlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+/// Limits applied to every instance unless its record overrides them.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LimitSettings {
+    /// Shared by all requests addressed to an instance.
+    pub instance: RateLimit,
+    /// Applied on top of the instance limit to requests sending an `X-Api-Key`.
+    pub api_key: RateLimit,
+    pub max_records: Option<u64>,
+    pub max_storage_bytes: Option<u64>,
+    /// How long instance overrides are cached before they are read again.
+    pub refresh_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UsageSettings {
+    /// How often metered usage is written to the `usage` table.
+    pub aggregation_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecycleBinSettings {
+    /// How long deleted records and objects can be restored before they are purged.
+    pub retention_days: u64,
+    /// How often expired records and objects are purged.
+    pub purge_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookSettings {
+    /// How often the outboxes of the instances are checked for due deliveries.
+    pub dispatch_interval_secs: u64,
+    /// How long a receiver has to respond to a delivery.
+    pub timeout_secs: u64,
+    /// How many deliveries are sent at a time.
+    pub delivery_concurrency: usize,
+    /// How often a delivery is attempted, and how long to wait between attempts.
+    pub retry: RetrySettings,
+    /// Hosts deliveries may be sent to even though they are or resolve to a
+    /// private address, written as in URLs.
+    pub allowed_hosts: Vec<String>,
+}
+
+/// A token bucket refilled at `requests_per_second` and holding up to `burst` requests.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetrySettings {
+    /// Default filter directives, used unless `RUST_LOG` is set.
+    pub level: String,
+    pub format: LogFormat,
+    pub file: Option<FileLogSettings>,
+    pub exporter: TraceExporter,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+pub enum LogFormat {
+    Pretty,
+    Compact,
+    Bunyan,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FileLogSettings {
+    pub directory: String,
+    pub file_name: String,
+    pub rotation: LogRotation,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+pub enum LogRotation {
+    Never,
+    Minutely,
+    Hourly,
+    Daily,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum TraceExporter {
+    None,
+    Jaeger {
+        endpoint: Option<String>,
+    },
+    Otlp {
+        endpoint: String,
+        #[serde(default)]
+        protocol: OtlpProtocol,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy, Default)]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    Http,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct DatabaseSettings {
     pub username: String,
+    #[serde(serialize_with = "redact")]
     pub password: Secret<String>,
-    pub database_name: String,
+    pub auth_level: AuthLevel,
+    #[serde(serialize_with = "redact")]
+    pub credentials_key: Secret<String>,
     pub connection: ConnectionType,
+    pub pool: PoolSettings,
+    pub retry: RetrySettings,
+    pub instance_migration_concurrency: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetrySettings {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PoolSettings {
+    pub min_connections: usize,
+    pub max_connections: usize,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub health_check_interval_secs: u64,
+}
+
+/// The level at which the configured database user is defined.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Copy)]
+pub enum AuthLevel {
+    Root,
+    Namespace,
+    Database,
+}
+
+impl Display for AuthLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthLevel::Root => write!(f, "root"),
+            AuthLevel::Namespace => write!(f, "namespace"),
+            AuthLevel::Database => write!(f, "database"),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ConnectionSettings {
     pub port: u16,
     pub host: String,
+    /// Connects over `wss://` instead of `ws://`. Required for remote databases.
+    #[serde(default)]
+    pub tls: bool,
 }
 
 impl ConnectionSettings {
     fn get_conn_string(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+        let scheme = if self.tls { "wss" } else { "ws" };
+        format!("{scheme}://{}:{}", self.host, self.port)
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 #[serde(tag = "type")]
 pub enum ConnectionType {
     InMemory,
@@ -48,27 +255,53 @@ impl ConnectionType {
     pub fn get_conn_string(&self) -> String {
         match &self {
             Self::InMemory => "mem://".into(),
-            Self::Local(settings) => format!("ws://{}", settings.get_conn_string()),
-            Self::Remote(settings) => format!("wss://{}", settings.get_conn_string()),
+            Self::Local(settings) | Self::Remote(settings) => settings.get_conn_string(),
         }
     }
 }
 
 impl DatabaseSettings {
-    pub fn get_root_credentials(&self) -> Root {
+    pub fn get_root_credentials(&self) -> Root<'_> {
         Root {
             username: &self.username,
             password: self.password.expose_secret(),
         }
     }
+
+    pub fn get_namespace_credentials(&self) -> Namespace<'_> {
+        Namespace {
+            namespace: ROOT_NAMESPACE,
+            username: &self.username,
+            password: self.password.expose_secret(),
+        }
+    }
+
+    pub fn get_database_credentials(&self) -> Database<'_> {
+        Database {
+            namespace: ROOT_NAMESPACE,
+            database: ROOT_DATABASE,
+            username: &self.username,
+            password: self.password.expose_secret(),
+        }
+    }
 }
 
+const ENV_PREFIX: &str = "APP";
+const ENV_SEPARATOR: &str = "__";
+const SECRET_FILE_SUFFIX: &str = "_FILE";
+
+/// Loads `config.yaml` and `config.{APP_ENVIRONMENT}.yaml` from `APP_CONFIG_DIR`
+/// (`./config` by default), then applies `APP__SECTION__KEY` environment variables
+/// and `APP__SECTION__KEY_FILE` secrets files on top.
 #[tracing::instrument(name = "Loading configuration")]
 pub fn get_configuration() -> Result<Settings, ConfigError> {
     tracing::debug!("Loading configuration");
-    let base_path = std::env::current_dir().expect("Failed to determine the current directory");
-    tracing::trace!("Config base path is: {:?}", base_path);
-    let configuration_directory = base_path.join("config");
+    let configuration_directory = match std::env::var("APP_CONFIG_DIR") {
+        Ok(directory) => PathBuf::from(directory),
+        Err(_) => std::env::current_dir()
+            .expect("Failed to determine the current directory")
+            .join("config"),
+    };
     tracing::trace!("Config directory path is: {:?}", configuration_directory);
 
     let environment: Environment = std::env::var("APP_ENVIRONMENT")
@@ -80,19 +313,248 @@ pub fn get_configuration() -> Result<Settings, ConfigError> {
     let environment_filename = format!("config.{}.yaml", environment.as_ref());
     tracing::trace!("Environment filename is: {:?}", environment_filename);
 
-    let settings = Config::builder()
+    let (variables, secret_files): (Vec<_>, Vec<_>) = std::env::vars()
+        .filter(|(key, _)| key.starts_with(&format!("{ENV_PREFIX}{ENV_SEPARATOR}")))
+        .partition(|(key, _)| !key.ends_with(SECRET_FILE_SUFFIX));
+
+    let mut builder = Config::builder()
         .add_source(config::File::from(
             configuration_directory.join("config.yaml"),
         ))
-        .add_source(config::File::from(
-            configuration_directory.join(environment_filename),
-        ))
-        .build()?;
-    tracing::trace!("Settings loaded {:?}", settings);
+        .add_source(
+            config::File::from(configuration_directory.join(environment_filename)).required(false),
+        )
+        .add_source(
+            config::Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator(ENV_SEPARATOR)
+                .separator(ENV_SEPARATOR)
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("webhooks.allowed_hosts")
+                .source(Some(variables.into_iter().collect())),
+        );
+
+    for (key, path) in secret_files {
+        let key = config_key(&key[..key.len() - SECRET_FILE_SUFFIX.len()]);
+        let secret = std::fs::read_to_string(&path).map_err(|e| {
+            ConfigError::Message(format!("Failed to read the secret file for `{key}`: {e}"))
+        })?;
+        builder = builder.set_override(key, secret.trim_end_matches(['\r', '\n']))?;
+    }
+
+    let settings = builder.build()?;
+
+    let settings = match settings.clone().try_deserialize::<Settings>() {
+        Ok(settings) => settings,
+        Err(e) => {
+            // Reports the problem with the path of its key, after every
+            // unknown key.
+            let errors = settings
+                .try_deserialize::<serde_json::Value>()
+                .map(keys::key_errors::<Settings>)
+                .unwrap_or_default();
+            if errors.is_empty() {
+                return Err(e);
+            }
+            return Err(ConfigError::Message(format!(
+                "Invalid configuration:\n  - {}",
+                errors.join("\n  - ")
+            )));
+        }
+    };
+
+    let errors = settings.validate(&environment);
+    if !errors.is_empty() {
+        return Err(ConfigError::Message(format!(
+            "Invalid configuration:\n  - {}",
+            errors.join("\n  - ")
+        )));
+    }
 
-    let settings = settings.try_deserialize::<Settings>();
     tracing::debug!("Configuration loaded");
-    settings
+    Ok(settings)
+}
+
+fn redact<S: Serializer>(_: &Secret<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("[REDACTED]")
+}
+
+impl Settings {
+    /// Checks value ranges and combinations of settings, returning every problem found.
+    pub fn validate(&self, environment: &Environment) -> Vec<String> {
+        let mut errors = Vec::new();
+        let Settings {
+            database,
+            application,
+            limits,
+            usage,
+            recycle_bin,
+            webhooks,
+            telemetry,
+        } = self;
+
+        if application
+            .admin_port
+            .is_some_and(|port| port == application.port)
+        {
+            errors.push("`application.admin_port` must differ from `application.port`".into());
+        }
+
+        if let Some(tls) = &application.tls {
+            let files = [
+                ("application.tls.cert_path", &tls.cert_path),
+                ("application.tls.key_path", &tls.key_path),
+            ]
+            .into_iter()
+            .chain(tls.certificates.iter().flat_map(|certificate| {
+                [
+                    (
+                        "application.tls.certificates.cert_path",
+                        &certificate.cert_path,
+                    ),
+                    (
+                        "application.tls.certificates.key_path",
+                        &certificate.key_path,
+                    ),
+                ]
+            }));
+            for (key, path) in files {
+                if !path.is_file() {
+                    errors.push(format!("`{key}` `{}` does not exist", path.display()));
+                }
+            }
+            if tls.certificates.iter().any(|c| c.domain.is_empty()) {
+                errors.push("`application.tls.certificates.domain` must not be empty".into());
+            }
+            if tls.reload_interval_secs == 0 {
+                errors.push("`application.tls.reload_interval_secs` must be at least 1".into());
+            }
+        }
+
+        match &database.connection {
+            ConnectionType::InMemory if matches!(environment, Environment::Prod) => {
+                errors.push("`database.connection` cannot be InMemory in prod".into());
+            }
+            ConnectionType::Remote(connection) if !connection.tls => {
+                errors.push("`database.connection.tls` must be enabled for Remote".into());
+            }
+            _ => {}
+        }
+        if let ConnectionType::Local(connection) | ConnectionType::Remote(connection) =
+            &database.connection
+        {
+            if connection.port == 0 {
+                errors.push("`database.connection.port` must not be 0".into());
+            }
+        }
+
+        if database.password.expose_secret().is_empty()
+            && database.connection != ConnectionType::InMemory
+        {
+            errors.push("`database.password` must not be empty".into());
+        }
+        if database.credentials_key.expose_secret().is_empty() {
+            errors.push(
+                "`database.credentials_key` must be set, with `APP__DATABASE__CREDENTIALS_KEY` \
+                or `APP__DATABASE__CREDENTIALS_KEY_FILE`"
+                    .into(),
+            );
+        } else if let Err(e) = SecretCipher::try_from(&database.credentials_key) {
+            errors.push(format!("`database.credentials_key` is invalid: {e}"));
+        }
+        if database.instance_migration_concurrency == 0 {
+            errors.push("`database.instance_migration_concurrency` must be at least 1".into());
+        }
+
+        let pool = &database.pool;
+        if pool.max_connections == 0 {
+            errors.push("`database.pool.max_connections` must be at least 1".into());
+        }
+        if pool.min_connections > pool.max_connections {
+            errors.push("`database.pool.min_connections` must not exceed `max_connections`".into());
+        }
+        if pool.acquire_timeout_secs == 0 {
+            errors.push("`database.pool.acquire_timeout_secs` must be at least 1".into());
+        }
+
+        let retry = &database.retry;
+        if retry.max_attempts == 0 {
+            errors.push("`database.retry.max_attempts` must be at least 1".into());
+        }
+        if retry.initial_backoff_ms > retry.max_backoff_ms {
+            errors.push(
+                "`database.retry.initial_backoff_ms` must not exceed `max_backoff_ms`".into(),
+            );
+        }
+
+        for (key, limit) in [
+            ("limits.instance", &limits.instance),
+            ("limits.api_key", &limits.api_key),
+        ] {
+            if !limit.requests_per_second.is_finite() || limit.requests_per_second <= 0.0 {
+                errors.push(format!("`{key}.requests_per_second` must be positive"));
+            }
+            if limit.burst == 0 {
+                errors.push(format!("`{key}.burst` must be at least 1"));
+            }
+        }
+        if limits.refresh_interval_secs == 0 {
+            errors.push("`limits.refresh_interval_secs` must be at least 1".into());
+        }
+
+        if usage.aggregation_interval_secs == 0 {
+            errors.push("`usage.aggregation_interval_secs` must be at least 1".into());
+        }
+
+        if recycle_bin.purge_interval_secs == 0 {
+            errors.push("`recycle_bin.purge_interval_secs` must be at least 1".into());
+        }
+
+        if webhooks.dispatch_interval_secs == 0 {
+            errors.push("`webhooks.dispatch_interval_secs` must be at least 1".into());
+        }
+        if webhooks.timeout_secs == 0 {
+            errors.push("`webhooks.timeout_secs` must be at least 1".into());
+        }
+        if webhooks.delivery_concurrency == 0 {
+            errors.push("`webhooks.delivery_concurrency` must be at least 1".into());
+        }
+        if webhooks.retry.max_attempts == 0 {
+            errors.push("`webhooks.retry.max_attempts` must be at least 1".into());
+        }
+        if webhooks.retry.initial_backoff_ms > webhooks.retry.max_backoff_ms {
+            errors.push(
+                "`webhooks.retry.initial_backoff_ms` must not exceed `max_backoff_ms`".into(),
+            );
+        }
+        for host in &webhooks.allowed_hosts {
+            // Hosts are compared with those of webhook URLs, which are normalized.
+            let url = Url::parse(&format!("http://{host}/"));
+            if url.ok().as_ref().and_then(Url::host_str) != Some(host.as_str()) {
+                errors.push(format!(
+                    "`webhooks.allowed_hosts` must hold hosts as written in URLs, not `{host}`"
+                ));
+            }
+        }
+
+        if let Err(e) = EnvFilter::try_new(&telemetry.level) {
+            errors.push(format!("`telemetry.level` is invalid: {e}"));
+        }
+
+        errors
+    }
+}
+
+/// Turns `APP__DATABASE__PASSWORD` into `database.password`.
+fn config_key(variable: &str) -> String {
+    let prefix = format!("{ENV_PREFIX}{ENV_SEPARATOR}");
+    variable
+        .strip_prefix(&prefix)
+        .unwrap_or(variable)
+        .split(ENV_SEPARATOR)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(".")
 }
 
 #[derive(Debug)]
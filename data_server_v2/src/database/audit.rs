@@ -0,0 +1,98 @@
+use serde_json::{Map, Value};
+use surrealdb::{engine::any::Any, method::Query, sql::Datetime, Surreal};
+
+use super::traced_query;
+use crate::model::audit::{AuditAction, AuditEvent, AuditQuery};
+
+/// An event for the audit log, which the change it records appends with
+/// [`APPEND_EVENT`] in its own transaction.
+#[derive(Debug, Clone)]
+pub struct NewEvent<'a> {
+    pub action: AuditAction,
+    pub actor: &'a str,
+    pub request_id: Option<&'a str>,
+    pub details: Map<String, Value>,
+}
+
+impl NewEvent<'_> {
+    /// Binds the parameters of [`APPEND_EVENT`] but `$target`, which the change sets.
+    pub(super) fn bind<'q>(&self, query: Query<'q, Any>) -> Query<'q, Any> {
+        query
+            .bind(("audit_action", self.action))
+            .bind(("audit_actor", self.actor.to_owned()))
+            .bind(("audit_request_id", self.request_id.map(str::to_owned)))
+            .bind(("details", self.details.clone()))
+    }
+}
+
+/// Appends the event of a change about `$target` to the audit log of the
+/// connected database. Runs in the transaction of the change, so that no change
+/// goes without its event.
+pub(super) const APPEND_EVENT: &str = "CREATE audit_event CONTENT {
+            action: $audit_action,
+            actor: $audit_actor,
+            request_id: $audit_request_id,
+            target: $target,
+            details: $details,
+        } RETURN NONE;";
+
+/// Sets `$details` to the fields that differ between the record `$before` and
+/// `$after` a change, either of which may be NONE, with both of their values.
+/// Missing fields are null.
+pub(super) const RECORD_CHANGES: &str = "LET $fields = array::union(
+            object::keys($before OR {}), object::keys($after OR {}));
+        LET $details = { changes: object::from_entries((
+            SELECT VALUE [$this, { before: $before[$this] ?? NULL, after: $after[$this] ?? NULL }]
+            FROM $fields WHERE $this != 'id' AND $before[$this] != $after[$this])) };";
+
+/// Lists the events of the connected database's audit log matching `query`,
+/// newest first, skipping `offset` and returning at most `limit`.
+pub async fn query_events(
+    db: &Surreal<Any>,
+    query: &AuditQuery,
+    offset: u32,
+    limit: u32,
+) -> anyhow::Result<Vec<AuditEvent>> {
+    let mut filters = Vec::new();
+    if query.action.is_some() {
+        filters.push("action = $action");
+    }
+    if query.actor.is_some() {
+        filters.push("actor = $actor");
+    }
+    if query.target.is_some() {
+        filters.push("target = $target");
+    }
+    if query.from.is_some() {
+        filters.push("at >= $from");
+    }
+    if query.to.is_some() {
+        filters.push("at < $to");
+    }
+    let filter = if filters.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", filters.join(" AND "))
+    };
+
+    let statement = format!(
+        // Formatted with a fixed precision, so that the timestamps sort as text.
+        "SELECT time::format(at, '%Y-%m-%dT%H:%M:%S%.6fZ') AS at, action, actor, request_id,
+            target, details
+        FROM audit_event{filter} ORDER BY at DESC LIMIT $limit START $offset"
+    );
+    let events = traced_query(
+        &statement,
+        db.query(&statement)
+            .bind(("action", query.action))
+            .bind(("actor", &query.actor))
+            .bind(("target", &query.target))
+            .bind(("from", query.from.map(Datetime::from)))
+            .bind(("to", query.to.map(Datetime::from)))
+            .bind(("limit", limit))
+            .bind(("offset", offset)),
+    )
+    .await?
+    .take(0)?;
+    Ok(events)
+}
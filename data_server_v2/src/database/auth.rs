@@ -0,0 +1,79 @@
+use anyhow::bail;
+use secrecy::{ExposeSecret, Secret};
+use surrealdb::{engine::any::Any, sql::Value, Surreal};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    transaction, DatabaseError,
+};
+use crate::configuration::{AuthLevel, ConnectionType, DatabaseSettings};
+
+/// Signs in with the configured credentials at the configured level.
+#[tracing::instrument(name = "Authenticating to the database", skip_all)]
+pub async fn authenticate(
+    db: &Surreal<Any>,
+    settings: &DatabaseSettings,
+) -> Result<(), DatabaseError> {
+    if settings.connection == ConnectionType::InMemory {
+        tracing::debug!("Skipping authentication for the in-memory database");
+        return Ok(());
+    }
+
+    tracing::debug!("Signing in as {} user", settings.auth_level);
+    let result = match settings.auth_level {
+        AuthLevel::Root => db.signin(settings.get_root_credentials()).await,
+        AuthLevel::Namespace => db.signin(settings.get_namespace_credentials()).await,
+        AuthLevel::Database => db.signin(settings.get_database_credentials()).await,
+    };
+
+    result.map_err(|source| DatabaseError::Authentication {
+        level: settings.auth_level,
+        username: settings.username.clone(),
+        source,
+    })?;
+
+    tracing::debug!("Authentication success");
+    Ok(())
+}
+
+/// Defines a database user with the owner role at `level`, within the root
+/// namespace and database where the level requires one, along with its audit event.
+#[tracing::instrument(skip(db, password, event))]
+pub async fn define_admin_user(
+    db: &Surreal<Any>,
+    level: AuthLevel,
+    username: &str,
+    password: &Secret<String>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    // Both values are embedded in the statement, since it does not accept parameters.
+    if username.is_empty()
+        || !username
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+    {
+        bail!("`{username}` is not a valid user name");
+    }
+    if password.expose_secret().is_empty() || password.expose_secret().contains(['\'', '\\']) {
+        bail!("The password must not be empty or contain quotes or backslashes");
+    }
+
+    let level = match level {
+        AuthLevel::Root => "ROOT",
+        AuthLevel::Namespace => "NAMESPACE",
+        AuthLevel::Database => "DATABASE",
+    };
+    let query = db.query(format!(
+        "BEGIN TRANSACTION;
+        DEFINE USER {username} ON {level} PASSWORD '{password}' ROLES OWNER;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;",
+        password = password.expose_secret(),
+    ));
+    let _: Value = transaction(
+        "DEFINE USER ... ROLES OWNER",
+        event.bind(query.bind(("target", username))),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use surrealdb::{engine::any::Any, sql::Value, Surreal};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    traced_query, transaction,
+};
+
+const INSTANCE_DB_USER: &str = "rush_instance_user";
+const PASSWORD_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// The database level login assigned to a single instance.
+#[derive(Debug)]
+pub struct InstanceCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+impl InstanceCredentials {
+    fn generate() -> Self {
+        Self {
+            username: INSTANCE_DB_USER.into(),
+            password: generate_password(),
+        }
+    }
+}
+
+/// Generates a random alphanumeric password.
+pub fn generate_password() -> Secret<String> {
+    let password: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(PASSWORD_LENGTH)
+        .map(char::from)
+        .collect();
+
+    Secret::new(password)
+}
+
+/// The form in which instance credentials are persisted in the root database.
+#[derive(Debug, Deserialize, Serialize)]
+struct StoredCredentials {
+    instance_name: String,
+    username: String,
+    secret: String,
+}
+
+/// Encrypts and decrypts instance secrets using the configured credentials key.
+#[derive(Clone)]
+pub struct SecretCipher(ChaCha20Poly1305);
+
+impl std::fmt::Debug for SecretCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SecretCipher").field(&"[REDACTED]").finish()
+    }
+}
+
+impl TryFrom<&Secret<String>> for SecretCipher {
+    type Error = anyhow::Error;
+
+    fn try_from(key: &Secret<String>) -> Result<Self, Self::Error> {
+        let key = STANDARD
+            .decode(key.expose_secret())
+            .context("The credentials key is not valid base64")?;
+
+        if key.len() != 32 {
+            bail!("The credentials key must be exactly 32 bytes long");
+        }
+
+        Ok(Self(ChaCha20Poly1305::new(Key::from_slice(&key))))
+    }
+}
+
+impl SecretCipher {
+    pub fn encrypt(&self, plaintext: &Secret<String>) -> anyhow::Result<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext.expose_secret().as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt the secret"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+        Ok(STANDARD.encode(payload))
+    }
+
+    pub fn decrypt(&self, encoded: &str) -> anyhow::Result<Secret<String>> {
+        let payload = STANDARD
+            .decode(encoded)
+            .context("The stored secret is not valid base64")?;
+
+        if payload.len() <= NONCE_LENGTH {
+            bail!("The stored secret is too short");
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+        let plaintext = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt the secret"))?;
+
+        Ok(Secret::new(String::from_utf8(plaintext)?))
+    }
+}
+
+/// Instance names end up as namespace and database identifiers, so only the
+/// characters allowed in a subdomain are accepted.
+pub fn validate_instance_name(name: &str) -> anyhow::Result<()> {
+    let valid = !name.is_empty()
+        && name != "root"
+        && name
+            .chars()
+            .all(|char| char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-');
+
+    if !valid {
+        bail!("`{name}` is not a valid instance name");
+    }
+
+    Ok(())
+}
+
+/// Creates the namespace, database and database level user for a new instance
+/// and stores the encrypted credentials in the root database, along with the
+/// audit event of its creation.
+#[tracing::instrument(skip(db, cipher, event))]
+pub async fn provision_instance(
+    db: &Surreal<Any>,
+    cipher: &SecretCipher,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    validate_instance_name(name)?;
+    let credentials = InstanceCredentials::generate();
+
+    tracing::debug!("Defining the instance namespace, database and user");
+    // The statement embeds the generated password, so only a redacted form is traced.
+    let query = db.query(format!(
+        "DEFINE NAMESPACE `{name}`;
+        USE NS `{name}`;
+        DEFINE DATABASE `{name}`;
+        USE DB `{name}`;
+        {user}",
+        user = define_user(&credentials),
+    ));
+    traced_query("DEFINE USER ... ON DATABASE", query)
+        .await?
+        .check()?;
+
+    tracing::debug!("Storing the encrypted instance credentials");
+    let stored = StoredCredentials {
+        instance_name: name.into(),
+        username: credentials.username,
+        secret: cipher.encrypt(&credentials.password)?,
+    };
+
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        CREATE instance_credential CONTENT $credentials;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;"
+    );
+    let _: Value = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("credentials", stored))
+                .bind(("target", name)),
+        ),
+    )
+    .await?;
+
+    tracing::debug!("Instance provisioned");
+    Ok(())
+}
+
+/// Redefines the user of the instance database `db` is bound to, so that users
+/// provisioned with an earlier role get the current one.
+#[tracing::instrument(skip(db, credentials))]
+pub async fn define_instance_user(
+    db: &Surreal<Any>,
+    credentials: &InstanceCredentials,
+) -> anyhow::Result<()> {
+    traced_query(
+        "DEFINE USER ... ON DATABASE",
+        db.query(define_user(credentials)),
+    )
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// The instance user may view the schema but not change it, and only writes what
+/// table permissions allow, so that it cannot lift those permissions either.
+fn define_user(credentials: &InstanceCredentials) -> String {
+    format!(
+        "DEFINE USER {username} ON DATABASE PASSWORD '{password}' ROLES VIEWER;",
+        username = credentials.username,
+        password = credentials.password.expose_secret(),
+    )
+}
+
+/// Looks up and decrypts the credentials of an existing instance.
+#[tracing::instrument(skip(db, cipher))]
+pub async fn get_instance_credentials(
+    db: &Surreal<Any>,
+    cipher: &SecretCipher,
+    name: &str,
+) -> anyhow::Result<InstanceCredentials> {
+    let statement = "SELECT * FROM instance_credential WHERE instance_name = $name";
+    let stored: Option<StoredCredentials> =
+        traced_query(statement, db.query(statement).bind(("name", name)))
+            .await?
+            .take(0)?;
+
+    let stored = stored.ok_or_else(|| anyhow!("No credentials found for instance `{name}`"))?;
+
+    Ok(InstanceCredentials {
+        username: stored.username,
+        password: cipher.decrypt(&stored.secret)?,
+    })
+}
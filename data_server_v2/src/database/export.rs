@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt::Write};
+use surrealdb::{engine::any::Any, sql::Value, Surreal};
+
+use super::traced_query;
+
+const MIGRATION_TABLE: &str = "migration";
+
+#[derive(Debug, Deserialize)]
+struct DatabaseInfo {
+    #[serde(default)]
+    analyzers: BTreeMap<String, String>,
+    #[serde(default)]
+    functions: BTreeMap<String, String>,
+    #[serde(default)]
+    params: BTreeMap<String, String>,
+    #[serde(default)]
+    tables: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TableInfo {
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+    #[serde(default)]
+    indexes: BTreeMap<String, String>,
+    #[serde(default)]
+    events: BTreeMap<String, String>,
+}
+
+/// Writes the schema and records of the database `db` is bound to as a SurrealQL
+/// script that [`import`] can replay. Users and the migration history are left
+/// out, since every instance gets its own when it is provisioned.
+#[tracing::instrument(skip(db))]
+pub async fn export(db: &Surreal<Any>) -> anyhow::Result<String> {
+    let mut script = String::from("OPTION IMPORT;\n\n");
+
+    let statement = "INFO FOR DB";
+    let info: Option<DatabaseInfo> = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    let Some(mut info) = info else {
+        return Ok(script);
+    };
+    info.tables.remove(MIGRATION_TABLE);
+
+    for definition in info
+        .analyzers
+        .values()
+        .chain(info.functions.values())
+        .chain(info.params.values())
+    {
+        writeln!(script, "{definition};")?;
+    }
+
+    for (table, definition) in &info.tables {
+        writeln!(script, "\n{definition};")?;
+        let statement = format!("INFO FOR TABLE `{table}`");
+        let table: Option<TableInfo> = traced_query(&statement, db.query(&statement))
+            .await?
+            .take(0)?;
+        if let Some(table) = table {
+            for definition in table
+                .fields
+                .values()
+                .chain(table.indexes.values())
+                .chain(table.events.values())
+            {
+                writeln!(script, "{definition};")?;
+            }
+        }
+    }
+
+    script.push_str("\nBEGIN TRANSACTION;\n");
+    for table in info.tables.keys() {
+        let statement = "SELECT * FROM type::table($table)";
+        let records: Value = traced_query(statement, db.query(statement).bind(("table", table)))
+            .await?
+            .take(0)?;
+        let Value::Array(records) = records else {
+            continue;
+        };
+
+        for record in records.iter() {
+            let Value::Object(fields) = record else {
+                continue;
+            };
+            let Some(id) = fields.get("id") else {
+                continue;
+            };
+            match (fields.get("in"), fields.get("out")) {
+                (Some(Value::Thing(from)), Some(Value::Thing(to))) => {
+                    writeln!(script, "RELATE {from}->{id}->{to} CONTENT {record};")?
+                }
+                _ => writeln!(script, "UPDATE {id} CONTENT {record};")?,
+            }
+        }
+    }
+    script.push_str("COMMIT TRANSACTION;\n");
+
+    Ok(script)
+}
+
+/// Replays a script produced by [`export`] against the database `db` is bound to.
+#[tracing::instrument(skip(db, script))]
+pub async fn import(db: &Surreal<Any>, script: &str) -> anyhow::Result<()> {
+    traced_query("IMPORT", db.query(script)).await?.check()?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpMessage, HttpRequest};
+use anyhow::anyhow;
+use futures_util::{future::LocalBoxFuture, FutureExt};
+use std::ops::Deref;
+use surrealdb::{engine::any::Any, Surreal};
+
+use super::pool::{Binding, ConnectionPool, PooledConnection};
+use crate::{error::ApiError, middleware::request_id::RequestId, model::instance::InstanceName};
+
+/// Checks out a connection and sets `$request_id` on it, so that queries can
+/// correlate what they record with the request.
+async fn acquire(
+    pool: Option<Data<ConnectionPool>>,
+    binding: Binding,
+    request_id: Option<RequestId>,
+) -> Result<PooledConnection, ApiError> {
+    let pool =
+        pool.ok_or_else(|| ApiError::Internal(anyhow!("Connection pool is not configured")))?;
+
+    let db = pool.get(binding).await.map_err(|e| {
+        tracing::error!("Failed to acquire a database connection: {:?}", e);
+        ApiError::Unavailable(e)
+    })?;
+
+    // Set even when absent, as the connection may still hold the id of the
+    // request that used it before.
+    let request_id = request_id.map(|request_id| request_id.as_str().to_owned());
+    db.set("request_id", request_id).await?;
+
+    Ok(db)
+}
+
+/// A pooled connection bound to the root namespace and database.
+pub struct RootConnection(PooledConnection);
+
+impl Deref for RootConnection {
+    type Target = Surreal<Any>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromRequest for RootConnection {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<Data<ConnectionPool>>().cloned();
+        let request_id = req.extensions().get::<RequestId>().cloned();
+
+        async move {
+            Ok(RootConnection(
+                acquire(pool, Binding::Root, request_id).await?,
+            ))
+        }
+        .boxed_local()
+    }
+}
+
+/// A pooled connection authenticated with the credentials of the instance the
+/// request was addressed to.
+pub struct InstanceConnection(PooledConnection);
+
+impl Deref for InstanceConnection {
+    type Target = Surreal<Any>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromRequest for InstanceConnection {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let instance = req.extensions().get::<InstanceName>().cloned();
+        let pool = req.app_data::<Data<ConnectionPool>>().cloned();
+        let request_id = req.extensions().get::<RequestId>().cloned();
+
+        async move {
+            let instance =
+                instance.ok_or_else(|| ApiError::NotFound("No instance found".into()))?;
+            let binding = Binding::Instance(instance.to_string());
+
+            Ok(InstanceConnection(
+                acquire(pool, binding, request_id).await?,
+            ))
+        }
+        .boxed_local()
+    }
+}
+
+/// A pooled connection to the database of the instance the request was
+/// addressed to, as the root user, for the few requests that change its schema.
+pub struct InstanceSchemaConnection(PooledConnection);
+
+impl Deref for InstanceSchemaConnection {
+    type Target = Surreal<Any>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromRequest for InstanceSchemaConnection {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let instance = req.extensions().get::<InstanceName>().cloned();
+        let pool = req.app_data::<Data<ConnectionPool>>().cloned();
+        let request_id = req.extensions().get::<RequestId>().cloned();
+
+        async move {
+            let instance =
+                instance.ok_or_else(|| ApiError::NotFound("No instance found".into()))?;
+            let binding = Binding::InstanceSchema(instance.to_string());
+
+            Ok(InstanceSchemaConnection(
+                acquire(pool, binding, request_id).await?,
+            ))
+        }
+        .boxed_local()
+    }
+}
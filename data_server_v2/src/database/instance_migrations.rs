@@ -0,0 +1,190 @@
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use surrealdb::{engine::any::Any, Surreal};
+
+use super::{
+    credentials::{define_instance_user, get_instance_credentials},
+    migrations::{applied_migrations, instance_migrations, migrate, Migration},
+    objects::define_object_tables,
+    pool::{Binding, ConnectionPool},
+    traced_query,
+};
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    UpToDate,
+    Behind,
+    Failed,
+}
+
+/// The schema version of a single instance database, as last recorded by the migrator.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstanceMigrationStatus {
+    pub instance_name: String,
+    pub version: u32,
+    pub latest: u32,
+    pub state: MigrationState,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct StoredStatus {
+    instance_name: String,
+    version: u32,
+    state: MigrationState,
+    error: Option<String>,
+}
+
+fn latest_version(migrations: &[Migration]) -> u32 {
+    migrations.last().map_or(0, |migration| migration.version)
+}
+
+/// Applies pending instance migrations to a single instance database and records
+/// the outcome in the root database.
+#[tracing::instrument(name = "Migrating instance", skip(pool, migrations))]
+pub async fn migrate_instance(
+    pool: &ConnectionPool,
+    migrations: &[Migration],
+    name: &str,
+) -> anyhow::Result<()> {
+    let outcome = async {
+        let credentials = {
+            let root = pool.get(Binding::Root).await?;
+            get_instance_credentials(&root, pool.cipher(), name).await?
+        };
+        let db = pool.get(Binding::InstanceSchema(name.into())).await?;
+        let result = async {
+            migrate(&db, migrations).await?;
+            define_object_tables(&db).await?;
+            define_instance_user(&db, &credentials).await
+        }
+        .await;
+        let version = applied_migrations(&db)
+            .await
+            .ok()
+            .and_then(|applied| applied.last().map(|migration| migration.version))
+            .unwrap_or(0);
+        anyhow::Ok((version, result))
+    }
+    .await;
+
+    let (status, result) = match outcome {
+        Ok((version, Ok(_))) => (
+            StoredStatus {
+                instance_name: name.into(),
+                version,
+                state: MigrationState::UpToDate,
+                error: None,
+            },
+            Ok(()),
+        ),
+        Ok((version, Err(e))) => (failed(name, version, &e), Err(e)),
+        Err(e) => (failed(name, 0, &e), Err(e)),
+    };
+
+    if let Err(e) = &result {
+        tracing::error!("Failed to migrate instance {name}: {:?}", e);
+    }
+
+    let root = pool.get(Binding::Root).await?;
+    let statement =
+        "UPDATE type::thing('instance_migration', $status.instance_name) CONTENT $status";
+    traced_query(statement, root.query(statement).bind(("status", status)))
+        .await?
+        .check()?;
+
+    result
+}
+
+fn failed(name: &str, version: u32, error: &anyhow::Error) -> StoredStatus {
+    StoredStatus {
+        instance_name: name.into(),
+        version,
+        state: MigrationState::Failed,
+        error: Some(format!("{error:#}")),
+    }
+}
+
+/// Walks every instance in the root database and applies pending instance
+/// migrations, running at most `concurrency` migrations at a time. Stops early
+/// once `shutdown` is triggered.
+#[tracing::instrument(name = "Migrating instances", skip(pool, shutdown))]
+pub async fn migrate_instances(
+    pool: &ConnectionPool,
+    concurrency: usize,
+    shutdown: &Shutdown,
+) -> anyhow::Result<()> {
+    let migrations = instance_migrations()?;
+    let names: Vec<String> = {
+        let root = pool.get(Binding::Root).await?;
+        let statement = "SELECT VALUE name FROM instance";
+        traced_query(statement, root.query(statement))
+            .await?
+            .take(0)?
+    };
+
+    tracing::info!("Migrating {} instances", names.len());
+    // Migrations already running are finished, but none are started after shutdown.
+    let failures = stream::iter(names)
+        .take_while(|_| std::future::ready(!shutdown.is_triggered()))
+        .map(|name| {
+            let migrations = &migrations;
+            async move { migrate_instance(pool, migrations, &name).await.is_err() }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter(|failed| std::future::ready(*failed))
+        .count()
+        .await;
+
+    if shutdown.is_triggered() {
+        tracing::info!("Instance migrations interrupted by shutdown");
+    } else if failures > 0 {
+        tracing::warn!("{failures} instances failed to migrate");
+    } else {
+        tracing::info!("All instances migrated");
+    }
+
+    Ok(())
+}
+
+/// Reports the recorded schema version of every instance against the latest
+/// instance migration known to this build.
+pub async fn instance_migration_statuses(
+    db: &Surreal<Any>,
+) -> anyhow::Result<Vec<InstanceMigrationStatus>> {
+    let latest = latest_version(&instance_migrations()?);
+
+    let names = "SELECT VALUE name FROM instance";
+    let statuses = "SELECT instance_name, version, state, error FROM instance_migration";
+    let mut response = traced_query(
+        &format!("{names}; {statuses}"),
+        db.query(names).query(statuses),
+    )
+    .await?;
+    let mut names: Vec<String> = response.take(0)?;
+    names.sort();
+    let stored: Vec<StoredStatus> = response.take(1)?;
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let status = stored.iter().find(|status| status.instance_name == name);
+            let version = status.map_or(0, |status| status.version);
+            let state = match status {
+                Some(status) if status.state == MigrationState::Failed => MigrationState::Failed,
+                _ if version < latest => MigrationState::Behind,
+                _ => MigrationState::UpToDate,
+            };
+
+            InstanceMigrationStatus {
+                instance_name: name,
+                version,
+                latest,
+                state,
+                error: status.and_then(|status| status.error.clone()),
+            }
+        })
+        .collect())
+}
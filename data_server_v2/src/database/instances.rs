@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use surrealdb::{engine::any::Any, sql::Value, Surreal};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    credentials::{provision_instance, validate_instance_name, SecretCipher},
+    traced_query, transaction,
+};
+use crate::model::{
+    instance::{Instance, InstanceLimits, InstanceState, InstanceSummary},
+    plan::Plan,
+};
+
+/// The state, limit overrides and plan of an instance, which its entitlements
+/// follow from.
+#[derive(Debug, Default, Deserialize)]
+pub struct InstancePolicy {
+    /// Instances created before states were recorded have none and are active.
+    #[serde(default)]
+    pub state: InstanceState,
+    /// Instances created before limits were recorded have none.
+    pub limits: Option<InstanceLimits>,
+    pub plan: Option<Plan>,
+}
+
+/// Creates an instance record and provisions its database, removing the record
+/// and whatever was provisioned again if provisioning fails. The audit event is
+/// appended once provisioning succeeds. Instance migrations are left to the caller.
+#[tracing::instrument(skip(db, cipher, event))]
+pub async fn create_instance(
+    db: &Surreal<Any>,
+    cipher: &SecretCipher,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Instance> {
+    validate_instance_name(name)?;
+
+    tracing::info!("Attempting to saving new instance to the db");
+    let statement = "CREATE instance CONTENT $instance";
+    let instance: Option<Instance> = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("instance", Instance { name: name.into() })),
+    )
+    .await?
+    .take(0)?;
+    let instance = instance.ok_or_else(|| anyhow!("The instance was not created"))?;
+
+    tracing::info!("Provisioning the instance database");
+    if let Err(e) = provision_instance(db, cipher, name, event).await {
+        tracing::error!("Failed to provision instance database: {:?}", e);
+        // The namespace may not have been defined, so failing to remove it is fine.
+        let statement = format!("REMOVE NAMESPACE `{name}`");
+        let removed = match traced_query(&statement, db.query(&statement)).await {
+            Ok(response) => response.check().map(drop),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = removed {
+            tracing::debug!("Did not remove the instance namespace: {e}");
+        }
+        let statement = "DELETE instance_credential WHERE instance_name = $name;
+            DELETE instance WHERE name = $name;";
+        traced_query(statement, db.query(statement).bind(("name", name)))
+            .await?
+            .check()?;
+        return Err(e);
+    }
+
+    Ok(instance)
+}
+
+/// Lists every instance ordered by name.
+pub async fn list_instances(db: &Surreal<Any>) -> anyhow::Result<Vec<InstanceSummary>> {
+    let statement = "SELECT name, state FROM instance ORDER BY name";
+    let instances = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(instances)
+}
+
+/// Changes the state of an instance along with its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn set_instance_state(
+    db: &Surreal<Any>,
+    name: &str,
+    state: InstanceState,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $updated = (UPDATE instance SET state = $state WHERE name = $target RETURN name);
+        IF $updated {{
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $updated;"
+    );
+    let updated: Vec<InstanceSummary> = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("target", name))
+                .bind(("state", state)),
+        ),
+    )
+    .await?;
+
+    if updated.is_empty() {
+        bail!("No instance named `{name}` exists");
+    }
+    Ok(())
+}
+
+/// Returns the limit overrides of an instance, or `None` if no such instance exists.
+pub async fn get_instance_limits(
+    db: &Surreal<Any>,
+    name: &str,
+) -> anyhow::Result<Option<InstanceLimits>> {
+    let statement = "SELECT VALUE limits FROM instance WHERE name = $name";
+    let limits: Vec<Option<InstanceLimits>> =
+        traced_query(statement, db.query(statement).bind(("name", name)))
+            .await?
+            .take(0)?;
+    // Instances created before limits were recorded have none.
+    Ok(limits.into_iter().next().map(Option::unwrap_or_default))
+}
+
+/// Returns the state, limit overrides and plan of an instance, or `None` if no
+/// such instance exists.
+pub async fn get_instance_policy(
+    db: &Surreal<Any>,
+    name: &str,
+) -> anyhow::Result<Option<InstancePolicy>> {
+    let statement = "SELECT state, limits,
+            (SELECT name, entitlements FROM plan WHERE name = $parent.plan)[0] AS plan
+        FROM instance WHERE name = $name";
+    let policy = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    Ok(policy)
+}
+
+/// Replaces the limit overrides of an instance along with its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn set_instance_limits(
+    db: &Surreal<Any>,
+    name: &str,
+    limits: &InstanceLimits,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $updated = (UPDATE instance SET limits = $limits WHERE name = $target RETURN name);
+        IF $updated {{
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $updated;"
+    );
+    let updated: Vec<InstanceSummary> = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("target", name))
+                .bind(("limits", limits)),
+        ),
+    )
+    .await?;
+
+    if updated.is_empty() {
+        bail!("No instance named `{name}` exists");
+    }
+    Ok(())
+}
+
+/// Removes an instance together with its namespace, credentials and migration
+/// status, and appends its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn delete_instance(
+    db: &Surreal<Any>,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    let statement = "SELECT VALUE name FROM instance WHERE name = $name";
+    let existing: Vec<String> = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    if existing.is_empty() {
+        bail!("No instance named `{name}` exists");
+    }
+    // The name ends up in an identifier below, so it must be a valid one.
+    validate_instance_name(name)?;
+
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        REMOVE NAMESPACE `{name}`;
+        DELETE instance_credential WHERE instance_name = $target;
+        DELETE instance_migration WHERE instance_name = $target;
+        DELETE instance WHERE name = $target;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;"
+    );
+    let _: Value = transaction(
+        &statement,
+        event.bind(db.query(&statement).bind(("target", name))),
+    )
+    .await?;
+    Ok(())
+}
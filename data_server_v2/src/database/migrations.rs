@@ -0,0 +1,186 @@
+use anyhow::{anyhow, bail, Context};
+use include_dir::Dir;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use surrealdb::{engine::any::Any, Surreal};
+
+use super::{traced_query, DB_QUERIES};
+
+/// A single schema change, embedded from a `<version>_<name>.surql` file.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+    query: &'static str,
+}
+
+/// A migration that has already been recorded in the `migration` table.
+#[derive(Debug, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+}
+
+/// Returns the migrations for the root database, ordered by version.
+pub fn root_migrations() -> anyhow::Result<Vec<Migration>> {
+    let dir = DB_QUERIES
+        .get_dir("migrations/root")
+        .ok_or_else(|| anyhow!("Failed to find the root migrations"))?;
+
+    load_migrations(dir)
+}
+
+/// Returns the migrations applied to every instance database, ordered by version.
+pub fn instance_migrations() -> anyhow::Result<Vec<Migration>> {
+    let dir = DB_QUERIES
+        .get_dir("migrations/instance")
+        .ok_or_else(|| anyhow!("Failed to find the instance migrations"))?;
+
+    load_migrations(dir)
+}
+
+pub fn load_migrations(dir: &'static Dir<'static>) -> anyhow::Result<Vec<Migration>> {
+    let mut migrations = dir
+        .files()
+        .map(|file| {
+            let path = file.path();
+            let stem = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("Invalid migration file name: {path:?}"))?;
+
+            let (version, name) = stem
+                .split_once('_')
+                .with_context(|| format!("Migration {stem} must be named <version>_<name>"))?;
+
+            let query = file
+                .contents_utf8()
+                .with_context(|| format!("Migration {stem} is not valid utf-8"))?;
+
+            Ok(Migration {
+                version: version
+                    .parse()
+                    .with_context(|| format!("Migration {stem} has an invalid version"))?,
+                name: name.into(),
+                checksum: hex::encode(Sha256::digest(query.as_bytes())),
+                query,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    migrations.sort_by_key(|migration| migration.version);
+
+    if let Some(pair) = migrations
+        .windows(2)
+        .find(|pair| pair[0].version == pair[1].version)
+    {
+        bail!("Duplicate migration version {}", pair[0].version);
+    }
+
+    Ok(migrations)
+}
+
+/// Lists the migrations recorded in the database the connection is bound to.
+pub async fn applied_migrations(db: &Surreal<Any>) -> anyhow::Result<Vec<AppliedMigration>> {
+    let query = DB_QUERIES
+        .get_file("migration-table.surql")
+        .expect("Failed to find migration-table script")
+        .contents_utf8()
+        .expect("Failed to extract contents of migration-table script");
+
+    traced_query(query, db.query(query)).await?.check()?;
+
+    let statement = "SELECT version, name, checksum FROM migration ORDER BY version";
+    let applied = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+
+    Ok(applied)
+}
+
+/// Returns the migrations that have not been applied yet, failing if an applied
+/// migration was changed or is unknown to this build.
+pub async fn pending_migrations<'a>(
+    db: &Surreal<Any>,
+    migrations: &'a [Migration],
+) -> anyhow::Result<Vec<&'a Migration>> {
+    let applied = applied_migrations(db).await?;
+
+    for applied in &applied {
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.version == applied.version)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Migration {} ({}) was applied but is unknown to this build",
+                    applied.version,
+                    applied.name
+                )
+            })?;
+
+        if migration.checksum != applied.checksum {
+            bail!(
+                "Checksum mismatch for migration {} ({}): expected {}, found {}",
+                migration.version,
+                migration.name,
+                migration.checksum,
+                applied.checksum
+            );
+        }
+    }
+
+    Ok(migrations
+        .iter()
+        .filter(|migration| !applied.iter().any(|a| a.version == migration.version))
+        .collect())
+}
+
+/// Applies every pending migration, each in its own transaction, to the database
+/// the connection is bound to. Returns the versions that were applied.
+#[tracing::instrument(name = "Running migrations", skip_all)]
+pub async fn migrate(db: &Surreal<Any>, migrations: &[Migration]) -> anyhow::Result<Vec<u32>> {
+    let pending = pending_migrations(db, migrations).await?;
+    let mut applied = Vec::with_capacity(pending.len());
+
+    for migration in pending {
+        tracing::info!(
+            "Applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+
+        let statement = format!(
+            "BEGIN TRANSACTION;
+            {};
+            CREATE migration SET
+                version = $version,
+                name = $name,
+                checksum = $checksum,
+                applied_at = time::now();
+            COMMIT TRANSACTION;",
+            migration.query.trim().trim_end_matches(';')
+        );
+        traced_query(
+            &statement,
+            db.query(&statement)
+                .bind(("version", migration.version))
+                .bind(("name", &migration.name))
+                .bind(("checksum", &migration.checksum)),
+        )
+        .await?
+        .check()
+        .with_context(|| {
+            format!(
+                "Failed to apply migration {} ({})",
+                migration.version, migration.name
+            )
+        })?;
+
+        applied.push(migration.version);
+    }
+
+    tracing::debug!("Migrations up to date");
+    Ok(applied)
+}
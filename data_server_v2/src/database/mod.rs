@@ -1,45 +1,170 @@
 use include_dir::include_dir;
 use include_dir::Dir;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::future::IntoFuture;
 use surrealdb::engine::any::connect;
 use surrealdb::engine::any::Any;
+use surrealdb::error::Db;
+use surrealdb::method::Query;
+use surrealdb::opt::QueryResult;
 use surrealdb::Surreal;
+use tracing::Instrument;
 
-use crate::configuration::ConnectionType;
+use crate::configuration::AuthLevel;
 use crate::configuration::DatabaseSettings;
+use crate::metrics::{DB_QUERY_DURATION, DB_QUERY_ERRORS};
+
+pub mod audit;
+pub mod auth;
+pub mod credentials;
+pub mod export;
+pub mod extractors;
+pub mod instance_migrations;
+pub mod instances;
+pub mod migrations;
+pub mod objects;
+pub mod plans;
+pub mod pool;
+pub mod records;
+pub mod retry;
+pub mod usage;
+pub mod versions;
+pub mod webhooks;
+
+pub const ROOT_NAMESPACE: &str = "root";
+pub const ROOT_DATABASE: &str = "root";
 
-// pub static DB: Lazy<Surreal<Any>> = Lazy::new(Surreal::init); // TODO: need to get rid of singleton and implement a connection pool
 pub static DB_QUERIES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/database/resources");
 
-#[tracing::instrument(name = "Initializing the database")]
-pub async fn init_db(settings: DatabaseSettings) -> anyhow::Result<Surreal<Any>> {
-    tracing::info!("Initializing the database");
+/// Errors that prevent the database from being initialized on startup.
+#[derive(Debug, thiserror::Error)]
+pub enum DatabaseError {
+    #[error("Failed to connect to the database at {address}")]
+    Connection {
+        address: String,
+        #[source]
+        source: surrealdb::Error,
+    },
+    #[error("Failed to authenticate as {level} user `{username}`")]
+    Authentication {
+        level: AuthLevel,
+        username: String,
+        #[source]
+        source: surrealdb::Error,
+    },
+    #[error("Failed to prepare the root namespace and database")]
+    Setup(#[from] surrealdb::Error),
+    #[error("Failed to migrate the root database")]
+    Migration(#[source] anyhow::Error),
+}
+
+/// Awaits a SurrealDB query inside a client span, so that it nests under the span
+/// of the request or job that issued it, and records its duration and failures.
+pub async fn traced_query<Q, T>(statement: &str, query: Q) -> surrealdb::Result<T>
+where
+    Q: IntoFuture<Output = surrealdb::Result<T>>,
+{
+    let span = tracing::info_span!(
+        "SurrealDB query",
+        otel.kind = "client",
+        db.system = "surrealdb",
+        db.statement = statement,
+    );
+
+    let timer = DB_QUERY_DURATION.start_timer();
+    let result = query.into_future().instrument(span).await;
+    timer.observe_duration();
+
+    if result.is_err() {
+        DB_QUERY_ERRORS.inc();
+    }
+    result
+}
+
+/// Picks the error that failed a transaction out of the errors of its statements,
+/// as the other statements of a failed transaction only report that they were
+/// not executed.
+pub(crate) fn transaction_error(
+    errors: HashMap<usize, surrealdb::Error>,
+) -> Option<surrealdb::Error> {
+    errors
+        .into_iter()
+        .min_by_key(|(index, error)| {
+            (
+                matches!(error, surrealdb::Error::Db(Db::QueryNotExecuted)),
+                *index,
+            )
+        })
+        .map(|(_, error)| error)
+}
+
+/// Runs a transaction, failing with the error that failed it, and returns the
+/// result of its last statement.
+pub(crate) async fn transaction<R>(statement: &str, query: Query<'_, Any>) -> anyhow::Result<R>
+where
+    R: DeserializeOwned,
+    usize: QueryResult<R>,
+{
+    let mut response = traced_query(statement, query).await?;
+    if let Some(error) = transaction_error(response.take_errors()) {
+        return Err(error.into());
+    }
+    let last = response.num_statements().saturating_sub(1);
+    Ok(response.take(last)?)
+}
+
+/// Connects to the configured database and signs in with the configured credentials.
+pub async fn open_connection(settings: &DatabaseSettings) -> Result<Surreal<Any>, DatabaseError> {
     tracing::debug!("Attempting to connect to the database");
-    let db: Surreal<Any> = connect(settings.connection.get_conn_string()).await?;
+    let address = settings.connection.get_conn_string();
+    let db: Surreal<Any> = connect(&address)
+        .await
+        .map_err(|source| DatabaseError::Connection { address, source })?;
     tracing::debug!("Connection success");
 
-    if settings.connection == ConnectionType::InMemory {
-        tracing::debug!("Initializing in-memory database. This should be used for testing only.");
-        let query = DB_QUERIES
-            .get_file("init-db.surql")
-            .expect("Failed to find init-db script")
-            .contents_utf8()
-            .expect("Failed to extract contents of init-db script");
+    auth::authenticate(&db, settings).await?;
+    Ok(db)
+}
 
-        tracing::trace!("Running initialization query: {query}");
+#[tracing::instrument(name = "Initializing the database")]
+pub async fn init_db(settings: &DatabaseSettings) -> Result<Surreal<Any>, DatabaseError> {
+    tracing::info!("Initializing the database");
+    let db = retry::with_retry(
+        &settings.retry,
+        |e| matches!(e, DatabaseError::Connection { .. }),
+        || open_connection(settings),
+    )
+    .await?;
+
+    if settings.auth_level == AuthLevel::Root {
+        tracing::debug!("Defining root ns");
+        let statement = format!("DEFINE NAMESPACE {ROOT_NAMESPACE};");
+        traced_query(&statement, db.query(&statement))
+            .await?
+            .check()?;
+    }
 
-        db.query(query)
-            .await
-            .expect("Failed to run init_db script on database");
-        tracing::trace!("Initialization query success")
-    };
+    if settings.auth_level != AuthLevel::Database {
+        tracing::debug!("Defining root db");
+        let statement = format!(
+            "USE NS {ROOT_NAMESPACE};
+            DEFINE DATABASE {ROOT_DATABASE};"
+        );
+        traced_query(&statement, db.query(&statement))
+            .await?
+            .check()?;
+    }
 
     tracing::debug!("Accessing to root ns and root db");
-    db.use_ns("root")
-        .use_db("root")
-        .await
-        .expect("Could not use the root namespace or root db");
+    db.use_ns(ROOT_NAMESPACE).use_db(ROOT_DATABASE).await?;
     tracing::debug!("Accessing success");
 
+    let migrations = migrations::root_migrations().map_err(DatabaseError::Migration)?;
+    migrations::migrate(&db, &migrations)
+        .await
+        .map_err(DatabaseError::Migration)?;
+
     tracing::info!("Initialation success");
     Ok(db)
 }
@@ -0,0 +1,302 @@
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use surrealdb::{
+    engine::any::Any,
+    sql::{Datetime, Value},
+    Surreal,
+};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    traced_query, transaction,
+    webhooks::ENQUEUE_DELIVERIES,
+};
+use crate::model::{
+    object::{DeletedObject, NewObject, ObjectTable},
+    webhook::WebhookEvent,
+};
+
+/// Tables of an instance database that objects cannot be named after.
+const RESERVED_TABLES: &[&str] = &[
+    "object_table",
+    "object_field",
+    "has_field",
+    "migration",
+    "audit_event",
+    "record_version",
+    "webhook",
+    "webhook_delivery",
+];
+
+/// Object names become table names, so they must be plain identifiers.
+pub fn validate_object_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.len() > 64 {
+        bail!("Object names must be between 1 and 64 characters long");
+    }
+    if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        bail!("Object names must start with a lowercase letter");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        bail!("Object names may only contain lowercase letters, digits and underscores");
+    }
+    if RESERVED_TABLES.contains(&name) {
+        bail!("`{name}` is reserved");
+    }
+    Ok(())
+}
+
+/// Lists every object outside the recycle bin ordered by name.
+pub async fn list_objects(db: &Surreal<Any>) -> anyhow::Result<Vec<ObjectTable>> {
+    let statement = "SELECT name, published, system, settings FROM object_table
+        WHERE deleted_at = NONE ORDER BY name";
+    let objects = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(objects)
+}
+
+/// Counts the objects outside the recycle bin, which are those the object limit
+/// applies to.
+pub async fn count_objects(db: &Surreal<Any>) -> anyhow::Result<u64> {
+    let statement = "SELECT count() AS count FROM object_table WHERE deleted_at = NONE GROUP ALL";
+    let count: Option<u64> = traced_query(statement, db.query(statement))
+        .await?
+        .take("count")?;
+    Ok(count.unwrap_or_default())
+}
+
+/// Creates an unpublished object along with its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn create_object(
+    db: &Surreal<Any>,
+    object: &NewObject,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<ObjectTable> {
+    validate_object_name(&object.name)?;
+
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $created = (CREATE object_table CONTENT {{ name: $target, settings: $settings }}
+            RETURN name, published, system, settings)[0];
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;
+        RETURN $created;"
+    );
+    let created: Option<ObjectTable> = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("target", &object.name))
+                .bind(("settings", &object.settings)),
+        ),
+    )
+    .await?;
+    created.ok_or_else(|| anyhow!("The object was not created"))
+}
+
+/// Defines the table of an object and marks it as published along with its
+/// audit event, returning `None` if there is no such object outside the recycle bin.
+#[tracing::instrument(skip(db, event))]
+pub async fn publish_object(
+    db: &Surreal<Any>,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<ObjectTable>> {
+    // The name ends up in an identifier below, so it must be a valid one.
+    validate_object_name(name)?;
+
+    let statement = "SELECT VALUE name FROM object_table WHERE name = $name AND deleted_at = NONE";
+    let existing: Vec<String> = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    if existing.is_empty() {
+        return Ok(None);
+    }
+
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        {define}
+        LET $published = (UPDATE object_table SET published = true WHERE name = $object
+            RETURN name, published, system, settings)[0];
+        LET $data = {{ object: $published }};
+        {ENQUEUE_DELIVERIES}
+        LET $target = $object;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;
+        RETURN $published;",
+        define = define_table(name)
+    );
+    let published = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("object", name))
+                .bind(("event", WebhookEvent::ObjectPublished)),
+        ),
+    )
+    .await?;
+    Ok(published)
+}
+
+/// Defines the tables of every published object again, so that those published
+/// before the instance user lost its editor role let it write their records.
+#[tracing::instrument(skip(db))]
+pub async fn define_object_tables(db: &Surreal<Any>) -> anyhow::Result<()> {
+    let statement = "SELECT VALUE name FROM object_table WHERE published = true";
+    let names: Vec<String> = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    for name in names {
+        validate_object_name(&name)?;
+        let statement = define_table(&name);
+        traced_query(&statement, db.query(&statement))
+            .await?
+            .check()?;
+    }
+    Ok(())
+}
+
+/// The table of an object, whose records the instance user may write.
+fn define_table(name: &str) -> String {
+    format!("DEFINE TABLE `{name}` SCHEMALESS PERMISSIONS FULL;")
+}
+
+/// Reads an object outside the recycle bin.
+pub async fn get_object(db: &Surreal<Any>, name: &str) -> anyhow::Result<Option<ObjectTable>> {
+    let statement = "SELECT name, published, system, settings FROM object_table
+        WHERE name = $name AND deleted_at = NONE";
+    let object = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    Ok(object)
+}
+
+/// Moves an object and its records to the recycle bin along with its audit
+/// event, returning `None` if there is no such object outside it.
+#[tracing::instrument(skip(db, event))]
+pub async fn delete_object(
+    db: &Surreal<Any>,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<ObjectTable>> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $deleted = (UPDATE object_table SET deleted_at = time::now()
+            WHERE name = $target AND deleted_at = NONE
+            RETURN name, published, system, settings)[0];
+        IF $deleted {{
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $deleted;"
+    );
+    let deleted = transaction(
+        &statement,
+        event.bind(db.query(&statement).bind(("target", name))),
+    )
+    .await?;
+    Ok(deleted)
+}
+
+/// Lists the objects in the recycle bin, most recently deleted first.
+pub async fn list_deleted_objects(db: &Surreal<Any>) -> anyhow::Result<Vec<DeletedObject>> {
+    let statement = "SELECT name, <string> deleted_at AS deleted_at FROM object_table
+        WHERE deleted_at != NONE ORDER BY deleted_at DESC";
+    let objects = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(objects)
+}
+
+/// Takes an object out of the recycle bin along with its audit event, returning
+/// `None` if it is not there.
+#[tracing::instrument(skip(db, event))]
+pub async fn restore_object(
+    db: &Surreal<Any>,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<ObjectTable>> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $restored = (UPDATE object_table SET deleted_at = NONE
+            WHERE name = $target AND deleted_at != NONE
+            RETURN name, published, system, settings)[0];
+        IF $restored {{
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $restored;"
+    );
+    let restored = transaction(
+        &statement,
+        event.bind(db.query(&statement).bind(("target", name))),
+    )
+    .await?;
+    Ok(restored)
+}
+
+/// Deletes an object in the recycle bin for good, along with its fields, its
+/// records and their history, and appends its audit event. Returns `false` if
+/// it is not there.
+#[tracing::instrument(skip(db, event))]
+pub async fn purge_object(
+    db: &Surreal<Any>,
+    name: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<bool> {
+    // The name ends up in an identifier below, so it must be a valid one.
+    validate_object_name(name)?;
+
+    let statement = "SELECT VALUE published FROM object_table
+        WHERE name = $name AND deleted_at != NONE";
+    let published: Option<bool> = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    let Some(published) = published else {
+        return Ok(false);
+    };
+
+    let remove_table = if published {
+        format!("REMOVE TABLE `{name}`;")
+    } else {
+        String::new()
+    };
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $table = (SELECT VALUE id FROM object_table WHERE name = $name);
+        DELETE object_field WHERE id IN (SELECT VALUE out FROM has_field WHERE in IN $table);
+        DELETE has_field WHERE in IN $table;
+        DELETE record_version WHERE object = $name;
+        DELETE object_table WHERE name = $name;
+        {remove_table}
+        LET $target = $name;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;"
+    );
+    let _: Value = transaction(
+        &statement,
+        event.bind(db.query(&statement).bind(("name", name))),
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Lists the objects that were moved to the recycle bin before `cutoff`.
+pub async fn expired_objects(
+    db: &Surreal<Any>,
+    cutoff: DateTime<Utc>,
+) -> anyhow::Result<Vec<String>> {
+    // NONE sorts before every datetime, so it has to be excluded explicitly.
+    let statement =
+        "SELECT VALUE name FROM object_table WHERE deleted_at != NONE AND deleted_at < $cutoff";
+    let objects = traced_query(
+        statement,
+        db.query(statement).bind(("cutoff", Datetime::from(cutoff))),
+    )
+    .await?
+    .take(0)?;
+    Ok(objects)
+}
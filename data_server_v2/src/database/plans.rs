@@ -0,0 +1,92 @@
+use anyhow::bail;
+use surrealdb::{engine::any::Any, Surreal};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    traced_query, transaction,
+};
+use crate::model::{
+    instance::InstanceSummary,
+    plan::{Plan, PlanEntitlements},
+};
+
+/// Lists every plan ordered by name.
+pub async fn list_plans(db: &Surreal<Any>) -> anyhow::Result<Vec<Plan>> {
+    let statement = "SELECT name, entitlements FROM plan ORDER BY name";
+    let plans = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(plans)
+}
+
+pub async fn get_plan(db: &Surreal<Any>, name: &str) -> anyhow::Result<Option<Plan>> {
+    let statement = "SELECT name, entitlements FROM plan WHERE name = $name";
+    let plan = traced_query(statement, db.query(statement).bind(("name", name)))
+        .await?
+        .take(0)?;
+    Ok(plan)
+}
+
+/// Creates a plan, or replaces the entitlements of an existing one, along with
+/// its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn put_plan(
+    db: &Surreal<Any>,
+    name: &str,
+    entitlements: &PlanEntitlements,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Plan> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $plan = (UPDATE type::thing('plan', $target)
+            CONTENT {{ name: $target, entitlements: $entitlements }}
+            RETURN name, entitlements)[0];
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;
+        RETURN $plan;"
+    );
+    let plan: Option<Plan> = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("target", name))
+                .bind(("entitlements", entitlements)),
+        ),
+    )
+    .await?;
+    plan.ok_or_else(|| anyhow::anyhow!("The plan was not saved"))
+}
+
+/// Assigns an instance to a plan, or removes its plan when `plan` is `None`,
+/// along with its audit event.
+#[tracing::instrument(skip(db, event))]
+pub async fn set_instance_plan(
+    db: &Surreal<Any>,
+    name: &str,
+    plan: Option<&str>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<()> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $updated = (UPDATE instance SET plan = $plan WHERE name = $target RETURN name);
+        IF $updated {{
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $updated;"
+    );
+    let updated: Vec<InstanceSummary> = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("target", name))
+                .bind(("plan", plan)),
+        ),
+    )
+    .await?;
+
+    if updated.is_empty() {
+        bail!("No instance named `{name}` exists");
+    }
+    Ok(())
+}
@@ -0,0 +1,522 @@
+use anyhow::{anyhow, Context};
+use secrecy::ExposeSecret;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+use surrealdb::{
+    engine::any::{connect, Any},
+    opt::auth::Database,
+    Surreal,
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{interval, timeout},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::auth::authenticate;
+use super::credentials::{get_instance_credentials, InstanceCredentials, SecretCipher};
+use super::retry::with_retry;
+use super::{ROOT_DATABASE, ROOT_NAMESPACE};
+use crate::configuration::{ConnectionType, DatabaseSettings, PoolSettings};
+
+/// The namespace and database a pooled connection is bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Root,
+    /// The database of an instance, as its own user.
+    Instance(String),
+    /// The database of an instance, as the root user, to change its schema.
+    InstanceSchema(String),
+}
+
+/// Signing in as the user of an instance failed, as when the instance was
+/// recreated since its credentials were cached.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to sign in as the user of instance `{0}`")]
+struct SigninFailed(String);
+
+#[derive(Debug)]
+struct IdleConnection {
+    db: Surreal<Any>,
+    since: Instant,
+}
+
+impl IdleConnection {
+    fn new(db: Surreal<Any>) -> Self {
+        Self {
+            db,
+            since: Instant::now(),
+        }
+    }
+}
+
+/// A snapshot of how many connections the pool currently holds.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub max: usize,
+    pub active: usize,
+    pub idle: usize,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    settings: PoolSettings,
+    database: DatabaseSettings,
+    cipher: SecretCipher,
+    idle: Mutex<HashMap<Binding, Vec<IdleConnection>>>,
+    credentials: Mutex<HashMap<String, Arc<InstanceCredentials>>>,
+    permits: Arc<Semaphore>,
+    reconnecting: AtomicBool,
+    closed: CancellationToken,
+}
+
+/// A pool of database connections, each bound to either the root database or
+/// to the database of a single instance.
+#[derive(Debug, Clone)]
+pub struct ConnectionPool(Arc<PoolInner>);
+
+impl ConnectionPool {
+    /// Creates a pool seeded with the root connection returned by `init_db`.
+    pub fn new(settings: &DatabaseSettings, db: Surreal<Any>) -> anyhow::Result<Self> {
+        let mut pool_settings = settings.pool.clone();
+
+        if settings.connection == ConnectionType::InMemory {
+            // An in-memory datastore only exists behind the connection that created it,
+            // so that single connection is shared and rebound on every checkout.
+            pool_settings.min_connections = 1;
+            pool_settings.max_connections = 1;
+        }
+
+        if pool_settings.max_connections == 0 {
+            return Err(anyhow!("The pool must allow at least one connection"));
+        }
+
+        let inner = PoolInner {
+            permits: Arc::new(Semaphore::new(pool_settings.max_connections)),
+            settings: pool_settings,
+            database: settings.clone(),
+            cipher: SecretCipher::try_from(&settings.credentials_key)?,
+            idle: Mutex::new(HashMap::from([(
+                Binding::Root,
+                vec![IdleConnection::new(db)],
+            )])),
+            credentials: Mutex::new(HashMap::new()),
+            reconnecting: AtomicBool::new(false),
+            closed: CancellationToken::new(),
+        };
+
+        let pool = Self(Arc::new(inner));
+        pool.spawn_maintenance();
+        Ok(pool)
+    }
+
+    pub fn cipher(&self) -> &SecretCipher {
+        &self.0.cipher
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        let max = self.0.settings.max_connections;
+        PoolStatus {
+            max,
+            active: max - self.0.permits.available_permits(),
+            idle: self.0.idle_count(),
+        }
+    }
+
+    /// Whether the last attempt to open a connection failed, meaning the database
+    /// is currently unreachable and the pool is trying to reconnect.
+    pub fn is_reconnecting(&self) -> bool {
+        self.0.reconnecting.load(Ordering::Relaxed)
+    }
+
+    /// Closes idle connections and stops maintenance. Connections still checked out
+    /// are closed when they are returned, and no new ones can be checked out.
+    pub fn close(&self) {
+        self.0.closed.cancel();
+        self.0.lock_idle().clear();
+        self.0.lock_credentials().clear();
+    }
+
+    /// Checks out a connection bound to the given namespace and database,
+    /// waiting up to the configured acquire timeout for one to become available.
+    /// Credentials of an instance that no longer sign in are loaded again once.
+    #[tracing::instrument(name = "Acquiring pooled connection", skip(self))]
+    pub async fn get(&self, binding: Binding) -> anyhow::Result<PooledConnection> {
+        match self.checkout(&binding).await {
+            Err(e) if e.downcast_ref::<SigninFailed>().is_some() => {
+                if let Binding::Instance(name) = &binding {
+                    tracing::info!("Reloading the credentials of instance {name}");
+                    self.evict(name);
+                }
+                self.checkout(&binding).await
+            }
+            result => result,
+        }
+    }
+
+    async fn checkout(&self, binding: &Binding) -> anyhow::Result<PooledConnection> {
+        let binding = binding.clone();
+        let inner = &self.0;
+        let mut credentials = None;
+
+        loop {
+            if inner.closed.is_cancelled() {
+                return Err(anyhow!("The connection pool is closed"));
+            }
+
+            if let Binding::Instance(name) = &binding {
+                if credentials.is_none() && (inner.is_in_memory() || !inner.has_idle(&binding)) {
+                    credentials = Some(self.credentials(name).await?);
+                }
+            }
+
+            let permit = timeout(
+                Duration::from_secs(inner.settings.acquire_timeout_secs),
+                inner.permits.clone().acquire_owned(),
+            )
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for a database connection"))??;
+
+            if let Some(idle) = inner.take_idle(&binding) {
+                if !inner.is_in_memory() {
+                    if let Err(e) = idle.db.health().await {
+                        tracing::warn!("Discarding broken idle connection: {:?}", e);
+                        drop(permit);
+                        continue;
+                    }
+
+                    tracing::trace!("Reusing idle connection");
+                    return Ok(PooledConnection::new(idle.db, binding, inner, permit));
+                }
+
+                tracing::trace!("Rebinding the in-memory connection");
+                if let Err(e) = inner.bind(&idle.db, &binding, credentials.as_deref()).await {
+                    inner.release(Binding::Root, idle.db);
+                    return Err(e);
+                }
+                return Ok(PooledConnection::new(idle.db, binding, inner, permit));
+            }
+
+            if matches!(binding, Binding::Instance(_)) && credentials.is_none() {
+                // The idle connection was taken by someone else in the meantime. Credentials
+                // are loaded through a root connection, so the permit must be given back first.
+                drop(permit);
+                continue;
+            }
+
+            inner.make_room();
+            tracing::debug!("Opening new pooled connection");
+            let db = inner.open(&binding, credentials.as_deref()).await?;
+            return Ok(PooledConnection::new(db, binding, inner, permit));
+        }
+    }
+
+    /// Drops idle connections and cached credentials for an instance, for example
+    /// after it has been deleted.
+    pub fn evict(&self, instance: &str) {
+        let mut idle = self.0.lock_idle();
+        idle.remove(&Binding::Instance(instance.into()));
+        idle.remove(&Binding::InstanceSchema(instance.into()));
+        drop(idle);
+        self.0.lock_credentials().remove(instance);
+    }
+
+    async fn credentials(&self, instance: &str) -> anyhow::Result<Arc<InstanceCredentials>> {
+        if let Some(credentials) = self.0.lock_credentials().get(instance) {
+            return Ok(credentials.clone());
+        }
+
+        tracing::debug!("Loading credentials for instance {instance}");
+        let root = Box::pin(self.get(Binding::Root)).await?;
+        let credentials =
+            Arc::new(get_instance_credentials(&root, &self.0.cipher, instance).await?);
+        drop(root);
+
+        self.0
+            .lock_credentials()
+            .insert(instance.into(), credentials.clone());
+        Ok(credentials)
+    }
+
+    fn spawn_maintenance(&self) {
+        let pool = Arc::downgrade(&self.0);
+        let period = Duration::from_secs(self.0.settings.health_check_interval_secs.max(1));
+
+        let closed = self.0.closed.clone();
+
+        actix_web::rt::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = closed.cancelled() => break,
+                }
+                let Some(inner) = Weak::upgrade(&pool) else {
+                    break;
+                };
+                inner.maintain().await;
+            }
+        });
+    }
+}
+
+impl PoolInner {
+    fn is_in_memory(&self) -> bool {
+        self.database.connection == ConnectionType::InMemory
+    }
+
+    fn lock_idle(&self) -> std::sync::MutexGuard<'_, HashMap<Binding, Vec<IdleConnection>>> {
+        self.idle.lock().expect("Connection pool lock poisoned")
+    }
+
+    fn lock_credentials(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, Arc<InstanceCredentials>>> {
+        self.credentials
+            .lock()
+            .expect("Connection pool lock poisoned")
+    }
+
+    fn idle_count(&self) -> usize {
+        self.lock_idle().values().map(Vec::len).sum()
+    }
+
+    fn has_idle(&self, binding: &Binding) -> bool {
+        self.lock_idle()
+            .get(binding)
+            .is_some_and(|idle| !idle.is_empty())
+    }
+
+    fn take_idle(&self, binding: &Binding) -> Option<IdleConnection> {
+        let mut idle = self.lock_idle();
+        if let Some(connection) = idle.get_mut(binding).and_then(Vec::pop) {
+            return Some(connection);
+        }
+
+        if self.is_in_memory() {
+            return idle.values_mut().find_map(Vec::pop);
+        }
+
+        None
+    }
+
+    fn release(&self, binding: Binding, db: Surreal<Any>) {
+        if self.closed.is_cancelled() {
+            return;
+        }
+
+        self.lock_idle()
+            .entry(binding)
+            .or_default()
+            .push(IdleConnection::new(db));
+    }
+
+    /// Closes the longest idle connection if opening another one would exceed the maximum.
+    fn make_room(&self) {
+        let active = self.settings.max_connections - self.permits.available_permits();
+        let mut idle = self.lock_idle();
+        let total = active + idle.values().map(Vec::len).sum::<usize>();
+
+        if total <= self.settings.max_connections {
+            return;
+        }
+
+        let oldest = idle
+            .iter()
+            .filter_map(|(binding, connections)| {
+                connections
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, connection)| connection.since)
+                    .map(|(index, connection)| (binding.clone(), index, connection.since))
+            })
+            .min_by_key(|(_, _, since)| *since);
+
+        if let Some((binding, index, _)) = oldest {
+            tracing::trace!("Closing idle connection to make room");
+            if let Some(connections) = idle.get_mut(&binding) {
+                connections.remove(index);
+            }
+        }
+    }
+
+    /// Opens and binds a new connection, retrying with backoff while the database
+    /// is unreachable, for at most the acquire timeout.
+    async fn open(
+        &self,
+        binding: &Binding,
+        credentials: Option<&InstanceCredentials>,
+    ) -> anyhow::Result<Surreal<Any>> {
+        let attempt = || async {
+            let db: Surreal<Any> = connect(self.database.connection.get_conn_string()).await?;
+            self.bind(&db, binding, credentials).await?;
+            anyhow::Ok(db)
+        };
+
+        let result = timeout(
+            Duration::from_secs(self.settings.acquire_timeout_secs),
+            // Retrying with the same credentials would not help.
+            with_retry(
+                &self.database.retry,
+                |e: &anyhow::Error| e.downcast_ref::<SigninFailed>().is_none(),
+                attempt,
+            ),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("Timed out reconnecting to the database")));
+
+        if result.is_err() {
+            if !self.reconnecting.swap(true, Ordering::Relaxed) {
+                tracing::error!("Lost connection to the database");
+            }
+        } else if self.reconnecting.swap(false, Ordering::Relaxed) {
+            tracing::info!("Reconnected to the database");
+        }
+
+        result
+    }
+
+    async fn bind(
+        &self,
+        db: &Surreal<Any>,
+        binding: &Binding,
+        credentials: Option<&InstanceCredentials>,
+    ) -> anyhow::Result<()> {
+        match binding {
+            Binding::Root => {
+                self.authenticate(db).await?;
+                db.use_ns(ROOT_NAMESPACE).use_db(ROOT_DATABASE).await?;
+            }
+            Binding::InstanceSchema(name) => {
+                self.authenticate(db).await?;
+                db.use_ns(name).use_db(name).await?;
+            }
+            Binding::Instance(name) => {
+                let credentials = credentials
+                    .with_context(|| format!("Missing credentials for instance {name}"))?;
+                db.signin(Database {
+                    namespace: name,
+                    database: name,
+                    username: &credentials.username,
+                    password: credentials.password.expose_secret(),
+                })
+                .await
+                .map_err(|e| anyhow::Error::new(e).context(SigninFailed(name.clone())))?;
+                db.use_ns(name).use_db(name).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Authenticates as the root user, which the in-memory datastore grants to
+    /// unauthenticated sessions.
+    async fn authenticate(&self, db: &Surreal<Any>) -> anyhow::Result<()> {
+        if self.is_in_memory() {
+            db.invalidate().await?;
+        } else {
+            authenticate(db, &self.database).await?;
+        }
+        Ok(())
+    }
+
+    /// Health checks idle connections, closes those idle for longer than the timeout
+    /// and opens root connections until the configured minimum is reached.
+    #[tracing::instrument(name = "Maintaining connection pool", skip(self))]
+    async fn maintain(&self) {
+        if self.is_in_memory() {
+            return;
+        }
+
+        let idle_timeout = Duration::from_secs(self.settings.idle_timeout_secs);
+        let idle: Vec<_> = self.lock_idle().drain().collect();
+
+        for (binding, connections) in idle {
+            for connection in connections {
+                if connection.since.elapsed() > idle_timeout {
+                    tracing::trace!("Closing expired idle connection");
+                    continue;
+                }
+
+                match connection.db.health().await {
+                    Ok(()) => self
+                        .lock_idle()
+                        .entry(binding.clone())
+                        .or_default()
+                        .push(connection),
+                    Err(e) => tracing::warn!("Closing unhealthy idle connection: {:?}", e),
+                }
+            }
+        }
+
+        let active = self.settings.max_connections - self.permits.available_permits();
+        let missing = self
+            .settings
+            .min_connections
+            .saturating_sub(active + self.idle_count());
+
+        for _ in 0..missing {
+            match self.open(&Binding::Root, None).await {
+                Ok(db) => self.release(Binding::Root, db),
+                Err(e) => {
+                    tracing::warn!("Failed to open idle root connection: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A connection checked out of the pool. It is returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledConnection {
+    db: Option<Surreal<Any>>,
+    binding: Binding,
+    pool: Arc<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    fn new(
+        db: Surreal<Any>,
+        binding: Binding,
+        pool: &Arc<PoolInner>,
+        permit: OwnedSemaphorePermit,
+    ) -> Self {
+        Self {
+            db: Some(db),
+            binding,
+            pool: pool.clone(),
+            _permit: permit,
+        }
+    }
+
+    pub fn binding(&self) -> &Binding {
+        &self.binding
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Surreal<Any>;
+
+    fn deref(&self) -> &Self::Target {
+        self.db
+            .as_ref()
+            .expect("Pooled connection used after release")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            self.pool.release(self.binding.clone(), db);
+        }
+    }
+}
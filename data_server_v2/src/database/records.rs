@@ -0,0 +1,528 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use std::fmt::Display;
+use surrealdb::{
+    engine::any::Any,
+    method::Query,
+    sql::{Datetime, Thing, Value},
+    Surreal,
+};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT, RECORD_CHANGES},
+    traced_query, transaction_error,
+    versions::{NewVersion, ADD_VERSION, DELETE_VERSIONS},
+    webhooks::ENQUEUE_DELIVERIES,
+};
+use crate::model::{object::ObjectTable, webhook::WebhookEvent};
+
+/// Records in the recycle bin have this field set to when they were deleted.
+pub const DELETED_AT: &str = "deleted_at";
+
+/// The number of records stored in the published object tables of an instance
+/// and their approximate size, measured as the length of their SurrealQL text.
+///
+/// Each object table keeps counters of its records, which every record write
+/// updates in the same transaction. Records and objects in the recycle bin are
+/// not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StorageUsage {
+    pub records: u64,
+    pub bytes: u64,
+}
+
+/// The storage quotas a record write is checked against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageQuota {
+    pub max_records: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+/// A record write that would have taken the instance beyond a storage quota.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// `records` or `storage_bytes`.
+    pub quota: String,
+    pub limit: u64,
+    /// The usage before the write.
+    pub used: u64,
+}
+
+/// Marks the errors thrown by [`CHARGE`], followed by `quota:limit:used`.
+const QUOTA_EXCEEDED: &str = "storage_quota:";
+
+impl QuotaExceeded {
+    /// Reads the error [`CHARGE`] throws, which remote engines only pass on as text.
+    fn from_thrown(error: &str) -> Option<Self> {
+        let (_, thrown) = error.split_once(QUOTA_EXCEEDED)?;
+        let mut parts = thrown.splitn(3, ':');
+        let quota = parts.next()?.into();
+        let limit = parts.next()?.parse().ok()?;
+        let used: String = parts
+            .next()?
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        let used = used.parse().ok()?;
+        Some(Self { quota, limit, used })
+    }
+}
+
+impl Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = if self.quota == "records" {
+            "records"
+        } else {
+            "bytes"
+        };
+        write!(f, "The instance may store at most {} {unit}", self.limit)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// Adds `$records` and `$bytes` to the counters of `$object` and throws if that
+/// takes the instance beyond `$max_records` or `$max_bytes`. Writes that do not
+/// grow a counter pass even when the instance is over that quota already.
+const CHARGE: &str = "UPDATE object_table SET records += $records, bytes += $bytes
+        WHERE name = $object;
+    LET $usage = (SELECT math::sum(records) AS records, math::sum(bytes) AS bytes
+        FROM object_table WHERE published = true AND deleted_at = NONE GROUP ALL)[0];
+    IF $records > 0 AND $max_records != NONE AND $usage.records > $max_records {
+        THROW 'storage_quota:records:' + <string> $max_records + ':'
+            + <string> ($usage.records - $records);
+    };
+    IF $bytes > 0 AND $max_bytes != NONE AND $usage.bytes > $max_bytes {
+        THROW 'storage_quota:storage_bytes:' + <string> $max_bytes + ':'
+            + <string> ($usage.bytes - $bytes);
+    };";
+
+/// A record as webhooks receive it, with the key of its id within its table.
+fn payload(record: &str) -> String {
+    format!("(SELECT *, meta::id(id) AS id FROM ONLY {record})")
+}
+
+/// Runs a transaction that writes a record, returning the result of the last
+/// statement. Fails with [`QuotaExceeded`] when [`CHARGE`] threw.
+async fn write(
+    statement: &str,
+    query: Query<'_, Any>,
+    quota: StorageQuota,
+) -> anyhow::Result<Value> {
+    let mut response = traced_query(
+        statement,
+        query
+            .bind(("max_records", quota.max_records))
+            .bind(("max_bytes", quota.max_bytes)),
+    )
+    .await?;
+    let errors = response.take_errors();
+    if let Some(exceeded) = errors
+        .values()
+        .find_map(|error| QuotaExceeded::from_thrown(&error.to_string()))
+    {
+        return Err(exceeded.into());
+    }
+    if let Some(error) = transaction_error(errors) {
+        return Err(error.into());
+    }
+    let last = response.num_statements().saturating_sub(1);
+    Ok(response.take(last)?)
+}
+
+/// Lists the names of the published object tables, including those in the recycle bin.
+pub async fn published_objects(db: &Surreal<Any>) -> anyhow::Result<Vec<String>> {
+    let statement = "SELECT VALUE name FROM object_table WHERE published = true ORDER BY name";
+    let objects = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(objects)
+}
+
+/// Reads the object table of a published object, or `None` if it is not published
+/// or in the recycle bin.
+pub async fn published_object(
+    db: &Surreal<Any>,
+    object: &str,
+) -> anyhow::Result<Option<ObjectTable>> {
+    let statement = "SELECT name, published, system, settings FROM object_table
+        WHERE name = $object AND published = true AND deleted_at = NONE";
+    let object = traced_query(statement, db.query(statement).bind(("object", object)))
+        .await?
+        .take(0)?;
+    Ok(object)
+}
+
+/// Lists the records of an object, except those in the recycle bin.
+pub async fn list_records(db: &Surreal<Any>, object: &str) -> anyhow::Result<Vec<JsonValue>> {
+    let statement = "SELECT * FROM type::table($object) WHERE deleted_at = NONE";
+    let records: Value = traced_query(statement, db.query(statement).bind(("object", object)))
+        .await?
+        .take(0)?;
+    Ok(into_records(records))
+}
+
+pub async fn get_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+) -> anyhow::Result<Option<JsonValue>> {
+    let statement = "SELECT * FROM type::thing($object, $id) WHERE deleted_at = NONE";
+    let records: Value = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("object", object))
+            .bind(("id", id)),
+    )
+    .await?
+    .take(0)?;
+    Ok(into_records(records).into_iter().next())
+}
+
+/// Creates a record with a generated id, along with its first version, its
+/// webhook deliveries and its audit event. An `id` or `deleted_at` in `content`
+/// is ignored.
+pub async fn create_record(
+    db: &Surreal<Any>,
+    object: &str,
+    mut content: Map<String, JsonValue>,
+    quota: StorageQuota,
+    version: &NewVersion<'_>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<JsonValue> {
+    content.remove("id");
+    content.remove(DELETED_AT);
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $after = (CREATE type::table($object) CONTENT $content)[0];
+        LET $records = 1;
+        LET $bytes = string::len(<string> $after);
+        {CHARGE}
+        LET $id = meta::id($after.id);
+        {ADD_VERSION}
+        LET $data = {{ record: {after} }};
+        {ENQUEUE_DELIVERIES}
+        LET $target = $object + ':' + <string> $id;
+        {RECORD_CHANGES}
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;
+        RETURN $after;",
+        after = payload("$after"),
+    );
+    let records = write(
+        &statement,
+        event.bind(
+            version.bind(
+                db.query(&statement)
+                    .bind(("object", object))
+                    .bind(("content", content))
+                    .bind(("event", WebhookEvent::RecordCreated)),
+            ),
+        ),
+        quota,
+    )
+    .await?;
+    into_records(records)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("The record was not created"))
+}
+
+/// A record as it was before and after an update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordUpdate {
+    pub before: JsonValue,
+    pub after: JsonValue,
+}
+
+/// Merges `content` into an existing record, returning `None` if there is none.
+pub async fn update_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    content: Map<String, JsonValue>,
+    quota: StorageQuota,
+    version: &NewVersion<'_>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<RecordUpdate>> {
+    write_record(db, "MERGE", object, id, content, quota, version, event).await
+}
+
+/// Replaces the content of an existing record, returning `None` if there is none.
+pub async fn replace_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    content: Map<String, JsonValue>,
+    quota: StorageQuota,
+    version: &NewVersion<'_>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<RecordUpdate>> {
+    write_record(db, "CONTENT", object, id, content, quota, version, event).await
+}
+
+/// Updates a record and adds its new version, its webhook deliveries and its
+/// audit event, charging the storage quotas with how much it grew.
+#[allow(clippy::too_many_arguments)]
+async fn write_record(
+    db: &Surreal<Any>,
+    clause: &str,
+    object: &str,
+    id: &str,
+    mut content: Map<String, JsonValue>,
+    quota: StorageQuota,
+    version: &NewVersion<'_>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<RecordUpdate>> {
+    content.remove("id");
+    content.remove(DELETED_AT);
+    // Updating a record id directly would create the record if it did not exist.
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $update = (UPDATE (SELECT VALUE id FROM type::thing($object, $id)
+                WHERE deleted_at = NONE)
+            {clause} $content
+            RETURN $before AS before, $after AS after)[0];
+        IF $update {{
+            LET $records = 0;
+            LET $bytes = string::len(<string> $update.after)
+                - string::len(<string> $update.before);
+            {CHARGE}
+            LET $after = $update.after;
+            {ADD_VERSION}
+            LET $data = {{ record: {after}, previous: {before} }};
+            {ENQUEUE_DELIVERIES}
+            LET $before = $update.before;
+            LET $target = $object + ':' + $id;
+            {RECORD_CHANGES}
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $update;",
+        after = payload("$update.after"),
+        before = payload("$update.before"),
+    );
+    let update = write(
+        &statement,
+        event.bind(
+            version.bind(
+                db.query(&statement)
+                    .bind(("object", object))
+                    .bind(("id", id))
+                    .bind(("content", content))
+                    .bind(("event", WebhookEvent::RecordUpdated)),
+            ),
+        ),
+        quota,
+    )
+    .await?;
+
+    let Value::Object(mut update) = update else {
+        return Ok(None);
+    };
+    let mut record = |version| {
+        let version = update.remove(version).unwrap_or_default();
+        into_records(version).into_iter().next()
+    };
+    Ok(record("before")
+        .zip(record("after"))
+        .map(|(before, after)| RecordUpdate { before, after }))
+}
+
+/// Moves a record to the recycle bin and queues its webhook deliveries and audit
+/// event, returning it or `None` if there was none.
+pub async fn delete_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<JsonValue>> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $before = (UPDATE (SELECT VALUE id FROM type::thing($object, $id)
+                WHERE deleted_at = NONE)
+            SET deleted_at = time::now() RETURN BEFORE)[0];
+        IF $before {{
+            LET $records = -1;
+            LET $bytes = 0 - string::len(<string> $before);
+            {CHARGE}
+            LET $data = {{ record: {before} }};
+            {ENQUEUE_DELIVERIES}
+            LET $target = $object + ':' + $id;
+            {RECORD_CHANGES}
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $before;",
+        before = payload("$before"),
+    );
+    let records = write(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("object", object))
+                .bind(("id", id))
+                .bind(("event", WebhookEvent::RecordDeleted)),
+        ),
+        StorageQuota::default(),
+    )
+    .await?;
+    Ok(into_records(records).into_iter().next())
+}
+
+/// Lists the records of an object in the recycle bin.
+pub async fn list_deleted_records(
+    db: &Surreal<Any>,
+    object: &str,
+) -> anyhow::Result<Vec<JsonValue>> {
+    let statement =
+        "SELECT * FROM type::table($object) WHERE deleted_at != NONE ORDER BY deleted_at DESC";
+    let records: Value = traced_query(statement, db.query(statement).bind(("object", object)))
+        .await?
+        .take(0)?;
+    Ok(into_records(records))
+}
+
+/// Takes a record out of the recycle bin along with its audit event, returning
+/// it or `None` if it is not there.
+pub async fn restore_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    quota: StorageQuota,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<JsonValue>> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $after = (UPDATE (SELECT VALUE id FROM type::thing($object, $id)
+                WHERE deleted_at != NONE)
+            SET deleted_at = NONE RETURN AFTER)[0];
+        IF $after {{
+            LET $records = 1;
+            LET $bytes = string::len(<string> $after);
+            {CHARGE}
+            LET $target = $object + ':' + $id;
+            {RECORD_CHANGES}
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $after;"
+    );
+    let records = write(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("object", object))
+                .bind(("id", id)),
+        ),
+        quota,
+    )
+    .await?;
+    Ok(into_records(records).into_iter().next())
+}
+
+/// Deletes a record in the recycle bin for good along with its history and its
+/// audit event, returning it or `None` if it is not there.
+pub async fn purge_record(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Option<JsonValue>> {
+    // Records in the recycle bin are not counted, so the counters stay as they are.
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $before = (DELETE (SELECT VALUE id FROM type::thing($object, $id)
+                WHERE deleted_at != NONE)
+            RETURN BEFORE)[0];
+        IF $before {{
+            {DELETE_VERSIONS}
+            LET $target = $object + ':' + $id;
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $before;"
+    );
+    let records = write(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("object", object))
+                .bind(("id", id)),
+        ),
+        StorageQuota::default(),
+    )
+    .await?;
+    Ok(into_records(records).into_iter().next())
+}
+
+/// Deletes the records of an object that were moved to the recycle bin before
+/// `cutoff` for good along with their history, with an audit event for each,
+/// returning them.
+pub async fn purge_expired_records(
+    db: &Surreal<Any>,
+    object: &str,
+    cutoff: DateTime<Utc>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Vec<JsonValue>> {
+    // NONE sorts before every datetime, so it has to be excluded explicitly.
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $purged = (DELETE type::table($object)
+            WHERE deleted_at != NONE AND deleted_at < $cutoff RETURN BEFORE);
+        FOR $record IN $purged {{
+            LET $id = meta::id($record.id);
+            {DELETE_VERSIONS}
+            LET $target = $object + ':' + <string> $id;
+            {APPEND_EVENT}
+        }};
+        COMMIT TRANSACTION;
+        RETURN $purged;"
+    );
+    let records = write(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("object", object))
+                .bind(("cutoff", Datetime::from(cutoff))),
+        ),
+        StorageQuota::default(),
+    )
+    .await?;
+    Ok(into_records(records))
+}
+
+/// Sums the counters of the published object tables outside the recycle bin.
+pub async fn storage_usage(db: &Surreal<Any>) -> anyhow::Result<StorageUsage> {
+    let statement = "SELECT math::sum(records) AS records, math::sum(bytes) AS bytes
+        FROM object_table WHERE published = true AND deleted_at = NONE GROUP ALL";
+    let usage: Option<StorageUsage> = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+    Ok(usage.unwrap_or_default())
+}
+
+/// Turns query results into JSON objects whose `id` is the key of the record
+/// within its table.
+fn into_records(value: Value) -> Vec<JsonValue> {
+    let records = match value {
+        Value::Array(records) => records.0,
+        Value::None | Value::Null => Vec::new(),
+        record => vec![record],
+    };
+
+    records
+        .into_iter()
+        .filter_map(|record| match record {
+            Value::Object(mut fields) => {
+                if let Some(Value::Thing(Thing { id, .. })) = fields.get("id") {
+                    let id = Value::from(id.to_raw());
+                    fields.insert("id".into(), id);
+                }
+                Some(Value::Object(fields).into_json())
+            }
+            _ => None,
+        })
+        .collect()
+}
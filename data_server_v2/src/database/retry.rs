@@ -0,0 +1,40 @@
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use tokio::time::sleep;
+
+use crate::configuration::RetrySettings;
+
+/// Runs `operation` until it succeeds, fails with an error that `is_transient`
+/// rejects, or the configured number of attempts is used up. The delay between
+/// attempts doubles every time, up to the configured maximum.
+pub async fn with_retry<T, E, F, Fut>(
+    settings: &RetrySettings,
+    is_transient: impl Fn(&E) -> bool,
+    mut operation: F,
+) -> Result<T, E>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let max_backoff = Duration::from_millis(settings.max_backoff_ms);
+    let mut backoff = Duration::from_millis(settings.initial_backoff_ms).min(max_backoff);
+    let mut attempt = 1;
+
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < settings.max_attempts && is_transient(&e) => {
+                tracing::warn!(
+                    "Attempt {attempt} of {} failed, retrying in {backoff:?}: {:?}",
+                    settings.max_attempts,
+                    e
+                );
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use surrealdb::{engine::any::Any, sql::Datetime, Surreal};
+
+use super::{records::StorageUsage, traced_query};
+use crate::model::usage::{HourlyUsage, UsageQuery};
+
+/// Adds metered requests to the usage of an instance during the hour starting at
+/// `hour`, and replaces its sampled storage when `storage` is given.
+#[tracing::instrument(skip(db, api_keys))]
+pub async fn record_usage(
+    db: &Surreal<Any>,
+    instance: &str,
+    hour: DateTime<Utc>,
+    requests: u64,
+    api_keys: Vec<String>,
+    storage: Option<StorageUsage>,
+) -> anyhow::Result<()> {
+    let statement = "UPDATE type::thing('usage', [$instance, $hour]) SET
+            instance = $instance,
+            hour = $hour,
+            api_requests = (api_requests OR 0) + $requests,
+            api_keys = array::union(api_keys OR [], $api_keys),
+            active_users = array::len(array::union(api_keys OR [], $api_keys)),
+            records = IF $storage THEN $storage.records ELSE records OR 0 END,
+            storage_bytes = IF $storage THEN $storage.bytes ELSE storage_bytes OR 0 END
+        RETURN NONE";
+    traced_query(
+        statement,
+        db.query(statement)
+            .bind(("instance", instance))
+            .bind(("hour", Datetime::from(hour)))
+            .bind(("requests", requests))
+            .bind(("api_keys", api_keys))
+            .bind(("storage", storage)),
+    )
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// Lists the hourly usage in the queried range, ordered by hour and instance.
+pub async fn query_usage(
+    db: &Surreal<Any>,
+    query: &UsageQuery,
+) -> anyhow::Result<Vec<HourlyUsage>> {
+    let filter = if query.instance.is_some() {
+        " AND instance = $instance"
+    } else {
+        ""
+    };
+    let statement = format!(
+        "SELECT instance, <string> hour AS hour, api_requests, records, storage_bytes, active_users
+        FROM usage WHERE hour >= $from AND hour < $to{filter} ORDER BY hour, instance"
+    );
+    let usage = traced_query(
+        &statement,
+        db.query(&statement)
+            .bind(("from", Datetime::from(query.from)))
+            .bind(("to", Datetime::from(query.to)))
+            .bind(("instance", &query.instance)),
+    )
+    .await?
+    .take(0)?;
+    Ok(usage)
+}
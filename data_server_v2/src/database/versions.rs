@@ -0,0 +1,93 @@
+use chrono::{TimeDelta, Utc};
+use surrealdb::{engine::any::Any, method::Query, sql::Datetime, Surreal};
+
+use super::traced_query;
+use crate::model::{object::HistorySettings, version::RecordVersion};
+
+/// Who a record write is attributed to, and how much history of the record is
+/// kept, for the version the write adds.
+#[derive(Debug, Clone)]
+pub struct NewVersion<'a> {
+    pub actor: &'a str,
+    pub history: HistorySettings,
+}
+
+impl NewVersion<'_> {
+    /// Binds the parameters of [`ADD_VERSION`].
+    pub(super) fn bind<'q>(&self, query: Query<'q, Any>) -> Query<'q, Any> {
+        let cutoff = self
+            .history
+            .max_age_days
+            .and_then(|days| TimeDelta::try_days(i64::try_from(days).ok()?))
+            .and_then(|age| Utc::now().checked_sub_signed(age))
+            .map(Datetime::from);
+        query
+            .bind(("actor", self.actor.to_owned()))
+            .bind(("max_versions", self.history.max_versions))
+            .bind(("cutoff", cutoff))
+    }
+}
+
+/// Keeps `$after` as the next version of the record `$id` of `$object`, then
+/// drops the versions beyond what `$max_versions` and `$cutoff` retain. Runs in
+/// the transaction of the record write, so that no write goes without it.
+pub(super) const ADD_VERSION: &str = "IF $max_versions = 0 {
+        DELETE record_version WHERE object = $object AND record = $id;
+    } ELSE {
+        LET $version = (SELECT VALUE version FROM record_version
+            WHERE object = $object AND record = $id ORDER BY version DESC LIMIT 1)[0] OR 0;
+        CREATE record_version CONTENT {
+            object: $object,
+            record: $id,
+            version: $version + 1,
+            actor: $actor,
+            content: (SELECT * OMIT id FROM ONLY $after),
+        } RETURN NONE;
+        DELETE record_version WHERE object = $object AND record = $id AND version <= $version
+            AND (($max_versions AND version <= $version + 1 - $max_versions)
+                OR ($cutoff AND at < $cutoff));
+    };";
+
+/// Lists the kept versions of a record, newest first.
+pub async fn list_versions(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+) -> anyhow::Result<Vec<RecordVersion>> {
+    let statement = "SELECT version, <string> at AS at, actor, content FROM record_version
+        WHERE object = $object AND record = $id ORDER BY version DESC";
+    let versions = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("object", object))
+            .bind(("id", id)),
+    )
+    .await?
+    .take(0)?;
+    Ok(versions)
+}
+
+pub async fn get_version(
+    db: &Surreal<Any>,
+    object: &str,
+    id: &str,
+    version: u64,
+) -> anyhow::Result<Option<RecordVersion>> {
+    let statement = "SELECT version, <string> at AS at, actor, content FROM record_version
+        WHERE object = $object AND record = $id AND version = $version";
+    let version = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("object", object))
+            .bind(("id", id))
+            .bind(("version", version)),
+    )
+    .await?
+    .take(0)?;
+    Ok(version)
+}
+
+/// Drops the history of the record `$id` of `$object`. Runs in the transaction
+/// that purges the record, so that no versions outlive it.
+pub(super) const DELETE_VERSIONS: &str =
+    "DELETE record_version WHERE object = $object AND record = $id;";
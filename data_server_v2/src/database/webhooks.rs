@@ -0,0 +1,226 @@
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value as JsonValue;
+use std::time::Duration;
+use surrealdb::{
+    engine::any::Any,
+    sql::{Datetime, Duration as SqlDuration, Value},
+    Surreal,
+};
+
+use super::{
+    audit::{NewEvent, APPEND_EVENT},
+    credentials::SecretCipher,
+    traced_query, transaction,
+};
+use crate::model::webhook::{
+    DeliveryAttempt, DeliveryStatus, NewWebhook, Webhook, WebhookDelivery, WebhookEvent,
+};
+
+const WEBHOOK_FIELDS: &str = "meta::id(id) AS id, url, events, object, created_at";
+
+/// A delivery whose next attempt is due, with where to send it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DueDelivery {
+    pub id: String,
+    pub url: String,
+    /// The secret of the webhook, encrypted with the credentials key.
+    pub secret: String,
+    pub event: WebhookEvent,
+    pub object: String,
+    pub created_at: DateTime<Utc>,
+    /// How many attempts were made before.
+    pub attempts: u32,
+    pub data: JsonValue,
+}
+
+/// Deserializes query results through JSON, which turns datetimes into strings.
+fn from_value<T: DeserializeOwned>(value: Value) -> anyhow::Result<T> {
+    Ok(serde_json::from_value(value.into_json())?)
+}
+
+/// Creates a webhook whose deliveries are signed with `secret`, which is stored
+/// encrypted, along with its audit event.
+#[tracing::instrument(skip(db, cipher, secret, event))]
+pub async fn create_webhook(
+    db: &Surreal<Any>,
+    cipher: &SecretCipher,
+    webhook: &NewWebhook,
+    secret: &Secret<String>,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<Webhook> {
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $webhook = CREATE webhook CONTENT {{
+            url: $url,
+            events: $events,
+            object: $object,
+            secret: $secret,
+        }};
+        LET $target = meta::id($webhook[0].id);
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;
+        SELECT {WEBHOOK_FIELDS} FROM $webhook;"
+    );
+    let created: Value = transaction(
+        &statement,
+        event.bind(
+            db.query(&statement)
+                .bind(("url", &webhook.url))
+                .bind(("events", &webhook.events))
+                .bind(("object", &webhook.object))
+                .bind(("secret", cipher.encrypt(secret)?)),
+        ),
+    )
+    .await?;
+    from_value::<Vec<Webhook>>(created)?
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("The webhook was not created"))
+}
+
+pub async fn list_webhooks(db: &Surreal<Any>) -> anyhow::Result<Vec<Webhook>> {
+    let statement = format!("SELECT {WEBHOOK_FIELDS} FROM webhook ORDER BY created_at");
+    let webhooks: Value = traced_query(&statement, db.query(&statement))
+        .await?
+        .take(0)?;
+    from_value(webhooks)
+}
+
+pub async fn get_webhook(db: &Surreal<Any>, id: &str) -> anyhow::Result<Option<Webhook>> {
+    let statement = format!("SELECT {WEBHOOK_FIELDS} FROM type::thing('webhook', $id)");
+    let webhooks: Value = traced_query(&statement, db.query(&statement).bind(("id", id)))
+        .await?
+        .take(0)?;
+    Ok(from_value::<Vec<Webhook>>(webhooks)?.pop())
+}
+
+/// Deletes a webhook along with its deliveries and appends its audit event,
+/// returning whether it existed.
+#[tracing::instrument(skip(db, event))]
+pub async fn delete_webhook(
+    db: &Surreal<Any>,
+    id: &str,
+    event: &NewEvent<'_>,
+) -> anyhow::Result<bool> {
+    if get_webhook(db, id).await?.is_none() {
+        return Ok(false);
+    }
+    let statement = format!(
+        "BEGIN TRANSACTION;
+        LET $webhook = type::thing('webhook', $target);
+        DELETE webhook_delivery WHERE webhook = $webhook;
+        DELETE $webhook;
+        {APPEND_EVENT}
+        COMMIT TRANSACTION;"
+    );
+    let _: Value = transaction(
+        &statement,
+        event.bind(db.query(&statement).bind(("target", id))),
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Queues a delivery of `$event` with `$data` for every webhook subscribed to
+/// it, either for all objects or for `$object`. Runs in the transaction of the
+/// change the event is about, so that no change goes without its deliveries.
+pub(super) const ENQUEUE_DELIVERIES: &str = "FOR $webhook IN (SELECT VALUE id FROM webhook
+            WHERE $event IN events AND (object = NONE OR object = $object)) {
+            CREATE webhook_delivery CONTENT {
+                webhook: $webhook,
+                event: $event,
+                object: $object,
+                data: $data,
+            } RETURN NONE;
+        };";
+
+/// Lists the deliveries of a webhook, newest first.
+pub async fn list_deliveries(
+    db: &Surreal<Any>,
+    webhook: &str,
+    status: Option<DeliveryStatus>,
+    limit: u32,
+) -> anyhow::Result<Vec<WebhookDelivery>> {
+    let statement = "SELECT meta::id(id) AS id, event, object, status, created_at,
+            next_attempt_at, attempts, data
+        FROM webhook_delivery
+        WHERE webhook = type::thing('webhook', $webhook) AND (!$status OR status = $status)
+        ORDER BY created_at DESC LIMIT $limit";
+    let deliveries: Value = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("webhook", webhook))
+            .bind(("status", status))
+            .bind(("limit", limit)),
+    )
+    .await?
+    .take(0)?;
+    from_value(deliveries)
+}
+
+/// Claims up to `limit` pending deliveries whose next attempt is due, oldest
+/// first, for `lease`. Deliveries claimed by another dispatcher are skipped
+/// until its claim runs out, so that every attempt is made by one dispatcher.
+pub async fn claim_due_deliveries(
+    db: &Surreal<Any>,
+    claimant: &str,
+    lease: Duration,
+    limit: u32,
+) -> anyhow::Result<Vec<DueDelivery>> {
+    let statement = "UPDATE (SELECT id, created_at FROM webhook_delivery
+            WHERE status = 'pending' AND next_attempt_at != NONE
+                AND next_attempt_at <= time::now()
+                AND (claimed_until = NONE OR claimed_until <= time::now())
+            ORDER BY created_at LIMIT $limit).id
+        SET claimed_by = $claimant, claimed_until = time::now() + $lease
+        WHERE claimed_until = NONE OR claimed_until <= time::now()
+        RETURN meta::id(id) AS id, webhook.url AS url, webhook.secret AS secret,
+            event, object, created_at, array::len(attempts) AS attempts, data";
+    let deliveries: Value = traced_query(
+        statement,
+        db.query(statement)
+            .bind(("claimant", claimant))
+            .bind(("lease", SqlDuration::from(lease)))
+            .bind(("limit", limit)),
+    )
+    .await?
+    .take(0)?;
+    from_value(deliveries)
+}
+
+/// Logs an attempt to deliver an event, and when to make the next one, if any,
+/// releasing the claim of `claimant`. Attempts made after the claim ran out and
+/// another dispatcher claimed the delivery are not logged.
+#[tracing::instrument(skip(db, attempt))]
+pub async fn record_attempt(
+    db: &Surreal<Any>,
+    claimant: &str,
+    id: &str,
+    attempt: &DeliveryAttempt,
+    status: DeliveryStatus,
+    next_attempt_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let statement = "UPDATE (SELECT VALUE id FROM type::thing('webhook_delivery', $id))
+        SET attempts += { at: $at, status_code: $status_code, error: $error },
+            status = $status,
+            next_attempt_at = $next_attempt_at,
+            claimed_by = NONE,
+            claimed_until = NONE
+        WHERE claimed_by = $claimant
+        RETURN NONE";
+    traced_query(
+        statement,
+        db.query(statement)
+            .bind(("claimant", claimant))
+            .bind(("id", id))
+            .bind(("at", Datetime::from(attempt.at)))
+            .bind(("status_code", attempt.status_code))
+            .bind(("error", &attempt.error))
+            .bind(("status", status))
+            .bind(("next_attempt_at", next_attempt_at.map(Datetime::from))),
+    )
+    .await?
+    .check()?;
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use actix_web::{
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::{header, StatusCode},
+    HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use surrealdb::error::Db;
+
+use crate::database::records::QuotaExceeded;
+
+/// Errors returned by request handlers and extractors, rendered as a JSON [`ErrorBody`].
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{message}")]
+    BadRequest {
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{message}")]
+    Conflict {
+        message: String,
+        details: Option<Value>,
+    },
+    /// The plan of the instance does not include a feature.
+    #[error("{message}")]
+    PaymentRequired {
+        message: String,
+        details: Option<Value>,
+    },
+    /// The instance has been suspended by an operator.
+    #[error("The instance `{0}` is suspended")]
+    Suspended(String),
+    /// A quota of the instance would be exceeded.
+    #[error("{message}")]
+    QuotaExceeded {
+        message: String,
+        details: Option<Value>,
+    },
+    #[error("Too many requests, retry after {} seconds", retry_after_secs(.retry_after))]
+    TooManyRequests { retry_after: Duration },
+    #[error("The service is temporarily unavailable")]
+    Unavailable(#[source] anyhow::Error),
+    #[error("An unexpected error occurred")]
+    Internal(#[source] anyhow::Error),
+}
+
+/// The body of every error response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Option<Value>,
+    pub request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::BadRequest {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest { .. } => "bad_request",
+            Self::NotFound(_) => "not_found",
+            Self::Conflict { .. } => "conflict",
+            Self::PaymentRequired { .. } => "payment_required",
+            Self::Suspended(_) => "instance_suspended",
+            Self::QuotaExceeded { .. } => "quota_exceeded",
+            Self::TooManyRequests { .. } => "too_many_requests",
+            Self::Unavailable(_) => "service_unavailable",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let details = match self {
+            Self::BadRequest { details, .. }
+            | Self::Conflict { details, .. }
+            | Self::PaymentRequired { details, .. }
+            | Self::QuotaExceeded { details, .. } => details.clone(),
+            Self::TooManyRequests { retry_after } => {
+                Some(json!({ "retry_after": retry_after_secs(retry_after) }))
+            }
+            _ => None,
+        };
+
+        ErrorBody {
+            code: self.code().into(),
+            message: self.to_string(),
+            details,
+            request_id: None,
+        }
+    }
+}
+
+impl ErrorBody {
+    /// Describes an error raised outside of this crate, such as by actix itself.
+    pub fn from_status(status: StatusCode, message: String) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::PAYMENT_REQUIRED => "payment_required",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+            StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+            StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+            status if status.is_server_error() => "internal_error",
+            _ => "error",
+        };
+
+        // Server errors may carry internals, so their message is not passed on.
+        let message = if status.is_server_error() {
+            "An unexpected error occurred".into()
+        } else {
+            message
+        };
+
+        Self {
+            code: code.into(),
+            message,
+            details: None,
+            request_id: None,
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict { .. } => StatusCode::CONFLICT,
+            Self::PaymentRequired { .. } => StatusCode::PAYMENT_REQUIRED,
+            Self::Suspended(_) | Self::QuotaExceeded { .. } => StatusCode::FORBIDDEN,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let Self::TooManyRequests { retry_after } = self {
+            response.insert_header((header::RETRY_AFTER, retry_after_secs(retry_after)));
+        }
+        response.json(self.body())
+    }
+}
+
+/// Rounds up to whole seconds, as `Retry-After` cannot express fractions.
+fn retry_after_secs(retry_after: &Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
+impl From<surrealdb::Error> for ApiError {
+    fn from(error: surrealdb::Error) -> Self {
+        match &error {
+            surrealdb::Error::Db(Db::IndexExists { index, value, .. }) => Self::Conflict {
+                message: "A record with the same unique value already exists".into(),
+                details: Some(json!({ "index": index, "value": value })),
+            },
+            // Remote engines only report the message of the database error.
+            surrealdb::Error::Api(surrealdb::error::Api::Query(message))
+                if message.starts_with("Database index")
+                    && message.contains("already contains") =>
+            {
+                Self::Conflict {
+                    message: "A record with the same unique value already exists".into(),
+                    details: None,
+                }
+            }
+            _ => Self::Internal(error.into()),
+        }
+    }
+}
+
+impl From<QuotaExceeded> for ApiError {
+    fn from(exceeded: QuotaExceeded) -> Self {
+        Self::QuotaExceeded {
+            message: exceeded.to_string(),
+            details: Some(json!({
+                "quota": exceeded.quota,
+                "limit": exceeded.limit,
+                "used": exceeded.used,
+            })),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<QuotaExceeded>() {
+            Ok(exceeded) => return exceeded.into(),
+            Err(error) => error,
+        };
+        match error.downcast::<surrealdb::Error>() {
+            Ok(error) => error.into(),
+            Err(error) => Self::Internal(error),
+        }
+    }
+}
+
+pub fn json_error_handler(error: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    // Oversized payloads and wrong content types keep their own status codes.
+    if error.status_code() != StatusCode::BAD_REQUEST {
+        return error.into();
+    }
+
+    ApiError::BadRequest {
+        message: "The request body is not valid".into(),
+        details: Some(json!({ "reason": error.to_string() })),
+    }
+    .into()
+}
+
+pub fn path_error_handler(error: PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest {
+        message: "The request path is not valid".into(),
+        details: Some(json!({ "reason": error.to_string() })),
+    }
+    .into()
+}
+
+pub fn query_error_handler(error: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::BadRequest {
+        message: "The query string is not valid".into(),
+        details: Some(json!({ "reason": error.to_string() })),
+    }
+    .into()
+}
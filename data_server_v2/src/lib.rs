@@ -2,33 +2,147 @@ use actix_web::{
     web::{self, Data},
     App, HttpServer,
 };
-use middleware::virtual_hosting::VirtualHostProcessor;
-use services::{health_check, instance::instance_service, root::root_service};
-use std::{io, net::TcpListener};
-use surrealdb::{engine::any::Any, Surreal};
+use configuration::{LimitSettings, UsageSettings, WebhookSettings};
+use database::pool::ConnectionPool;
+use futures_util::future::join_all;
+use limits::Limiter;
+use middleware::{
+    json_errors::JsonErrors, metrics::RequestMetrics, rate_limit::RateLimiter,
+    request_id::RequestIdProcessor, usage::UsageMetering, virtual_hosting::VirtualHostProcessor,
+};
+use rustls::ServerConfig;
+use services::{
+    health, health_check, instance::instance_service, metrics::metrics_service, root::root_service,
+};
+use shutdown::Shutdown;
+use std::{io, net::TcpListener, sync::Arc};
+use telemetry::InstanceRootSpanBuilder;
 use tracing_actix_web::TracingLogger;
+use usage::UsageMeter;
+use webhooks::{spawn_dispatch, Receivers};
 
+pub mod audit;
 pub mod configuration;
 pub mod database;
+pub mod error;
 mod guards;
+pub mod limits;
+pub mod metrics;
 mod middleware;
 pub mod model;
+pub mod recycle_bin;
 mod services;
+pub mod shutdown;
 pub mod telemetry;
+pub mod tls;
+pub mod usage;
+pub mod webhooks;
 
-pub async fn run(listener: TcpListener, db: Surreal<Any>) -> io::Result<()> {
+/// Serves the API on `listener`, over HTTPS when `tls` is given. Metrics are
+/// served on `admin_listener` when one is given, and alongside the API otherwise.
+/// Requests to instances are subject to `limits` and metered as configured by
+/// `usage`, and their webhooks are delivered as configured by `webhooks`.
+///
+/// Returns once `shutdown` has been triggered and in-flight requests have been
+/// drained, after writing the remaining usage and closing the connection pool.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    listener: TcpListener,
+    admin_listener: Option<TcpListener>,
+    tls: Option<ServerConfig>,
+    pool: ConnectionPool,
+    limits: LimitSettings,
+    usage: UsageSettings,
+    webhooks: WebhookSettings,
+    shutdown: Shutdown,
+) -> io::Result<()> {
+    let pool = Data::new(pool);
+    let limiter = Arc::new(Limiter::new(limits));
+    limiter.spawn_sweep(shutdown.clone());
+    let limiter = Data::from(limiter);
+    let meter = Arc::new(UsageMeter::new(usage));
+    meter.spawn_aggregation(ConnectionPool::clone(&pool), shutdown.clone());
+    let meter = Data::from(meter);
+    let app_meter = meter.clone();
+    let receivers = Data::new(Receivers::new(&webhooks.allowed_hosts));
+    spawn_dispatch(ConnectionPool::clone(&pool), webhooks, shutdown.clone());
+    let serve_metrics = admin_listener.is_none();
+    let app_pool = pool.clone();
     // TODO: create instance guard to handle directing to instance handling or main admin instance
     // TODO: set up proper tracing logs for existing endpoints and middleware
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(UsageMetering)
+            .wrap(RateLimiter)
             .wrap(VirtualHostProcessor)
-            .wrap(TracingLogger::default())
+            .wrap(JsonErrors)
+            .wrap(RequestMetrics)
+            .wrap(RequestIdProcessor)
+            .wrap(TracingLogger::<InstanceRootSpanBuilder>::new())
             .configure(root_service)
             .configure(instance_service)
+            .configure(|cfg| {
+                if serve_metrics {
+                    metrics_service(cfg);
+                }
+            })
             .route("/health_check", web::get().to(health_check))
-            .app_data(Data::new(db.clone()))
+            .route("/health/live", web::get().to(health::live))
+            .route("/health/ready", web::get().to(health::ready))
+            .app_data(app_pool.clone())
+            .app_data(limiter.clone())
+            .app_data(app_meter.clone())
+            .app_data(receivers.clone())
+            .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(error::path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
     })
-    .listen(listener)?
-    .run()
-    .await
+    .disable_signals()
+    .shutdown_timeout(shutdown.timeout().as_secs());
+    let server = match tls {
+        Some(tls) => server.listen_rustls_0_21(listener, tls)?,
+        None => server.listen(listener)?,
+    }
+    .run();
+
+    let admin_server = admin_listener
+        .map(|admin_listener| {
+            let pool = pool.clone();
+            HttpServer::new(move || {
+                App::new()
+                    .wrap(JsonErrors)
+                    .wrap(RequestIdProcessor)
+                    .wrap(TracingLogger::<InstanceRootSpanBuilder>::new())
+                    .configure(metrics_service)
+                    .app_data(pool.clone())
+            })
+            .disable_signals()
+            .shutdown_timeout(shutdown.timeout().as_secs())
+            .listen(admin_listener)
+            .map(HttpServer::run)
+        })
+        .transpose()?;
+
+    let handles: Vec<_> = std::iter::once(server.handle())
+        .chain(admin_server.as_ref().map(|server| server.handle()))
+        .collect();
+    actix_web::rt::spawn(async move {
+        shutdown.triggered().await;
+        tracing::info!("Draining in-flight requests");
+        join_all(handles.iter().map(|handle| handle.stop(true))).await;
+    });
+
+    match admin_server {
+        Some(admin_server) => futures_util::try_join!(server, admin_server).map(|_| ())?,
+        None => server.await?,
+    }
+
+    tracing::info!("Writing metered usage");
+    if let Err(e) = meter.aggregate(&pool).await {
+        tracing::error!("Failed to write metered usage: {:?}", e);
+    }
+
+    tracing::info!("Closing database connections");
+    pool.close();
+    Ok(())
 }
@@ -0,0 +1,366 @@
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    future::{ready, Ready},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::interval;
+
+use crate::{
+    configuration::{LimitSettings, RateLimit},
+    database::{
+        instances::{get_instance_policy, InstancePolicy},
+        pool::{Binding, ConnectionPool},
+        records::StorageQuota,
+    },
+    error::ApiError,
+    model::{instance::InstanceState, plan::Feature},
+    shutdown::Shutdown,
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How many instances entitlements are cached for. Requests to unknown hosts
+/// are cached as well, so this bounds what they can add between sweeps.
+const MAX_CACHED_ENTITLEMENTS: usize = 10_000;
+
+/// What an instance may use: the features of its plan and its limits, taken
+/// from its own overrides, then its plan, then the configured defaults.
+///
+/// Handlers extract it to check entitlements. It is only available on requests
+/// addressed to an instance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entitlements {
+    /// Suspended instances are entitled to nothing.
+    pub state: InstanceState,
+    pub plan: Option<String>,
+    /// `None` when the instance has no plan, which entitles it to every feature.
+    pub features: Option<Vec<Feature>>,
+    pub instance: RateLimit,
+    pub api_key: RateLimit,
+    // TODO: enforce once instances have users
+    pub max_users: Option<u64>,
+    pub max_objects: Option<u64>,
+    pub max_records: Option<u64>,
+    pub max_storage_bytes: Option<u64>,
+    pub max_daily_api_calls: Option<u64>,
+}
+
+impl Entitlements {
+    fn new(settings: &LimitSettings, policy: InstancePolicy) -> Self {
+        let overrides = policy.limits.unwrap_or_default();
+        let plan = policy.plan;
+        let entitlements = plan.as_ref().map(|plan| &plan.entitlements);
+
+        Self {
+            state: policy.state,
+            instance: overrides.instance.unwrap_or(settings.instance),
+            api_key: overrides.api_key.unwrap_or(settings.api_key),
+            max_users: entitlements.and_then(|e| e.max_users),
+            max_objects: entitlements.and_then(|e| e.max_objects),
+            max_records: overrides
+                .max_records
+                .or(entitlements.and_then(|e| e.max_records))
+                .or(settings.max_records),
+            max_storage_bytes: overrides.max_storage_bytes.or(settings.max_storage_bytes),
+            max_daily_api_calls: entitlements.and_then(|e| e.max_daily_api_calls),
+            features: entitlements.map(|e| e.features.clone()),
+            plan: plan.map(|plan| plan.name),
+        }
+    }
+
+    /// Fails with 402 Payment Required unless the plan includes `feature`.
+    pub fn require(&self, feature: Feature) -> Result<(), ApiError> {
+        match (&self.features, &self.plan) {
+            (Some(features), Some(plan)) if !features.contains(&feature) => {
+                Err(ApiError::PaymentRequired {
+                    message: format!(
+                        "The `{feature}` feature is not included in the `{plan}` plan"
+                    ),
+                    details: Some(json!({ "feature": feature, "plan": plan })),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if the instance may not have another object besides its `objects`.
+    pub fn check_objects(&self, objects: u64) -> Result<(), ApiError> {
+        match self.max_objects {
+            Some(max_objects) if objects >= max_objects => Err(ApiError::QuotaExceeded {
+                message: format!("The instance may have at most {max_objects} objects"),
+                details: Some(json!({ "quota": "objects", "limit": max_objects, "used": objects })),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The storage quotas record writes are checked against.
+    pub fn storage_quota(&self) -> StorageQuota {
+        StorageQuota {
+            max_records: self.max_records,
+            max_bytes: self.max_storage_bytes,
+        }
+    }
+}
+
+impl FromRequest for Entitlements {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Entitlements>()
+                .cloned()
+                .ok_or_else(|| ApiError::NotFound("No instance found".into())),
+        )
+    }
+}
+
+/// A request rejected by a rate limit.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+    /// `instance`, `api_key` or `daily_api_calls`.
+    pub limit: &'static str,
+    pub retry_after: Duration,
+}
+
+/// Tracks the token buckets of every instance and API key and the API calls of
+/// each instance per day, and caches the entitlements of each instance for
+/// `refresh_interval_secs`.
+pub struct Limiter {
+    settings: LimitSettings,
+    buckets: Mutex<HashMap<BucketKey, TokenBucket>>,
+    /// The day, counted from the Unix epoch, and the calls made on it.
+    daily_calls: Mutex<HashMap<String, (u64, u64)>>,
+    entitlements: Mutex<HashMap<String, (Instant, Option<Entitlements>)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Instance(String),
+    /// API keys are only kept as digests.
+    ApiKey {
+        instance: String,
+        digest: [u8; 32],
+    },
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    pub fn new(settings: LimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+            daily_calls: Mutex::new(HashMap::new()),
+            entitlements: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the entitlements of an instance, or `None` if no such instance
+    /// exists, reading its state, overrides and plan from the root database once
+    /// the cached ones have expired. Fails with 503 Service Unavailable while
+    /// they cannot be read, as suspensions and limits could not be enforced.
+    pub async fn entitlements(
+        &self,
+        pool: &ConnectionPool,
+        instance: &str,
+    ) -> Result<Option<Entitlements>, ApiError> {
+        let now = Instant::now();
+        if let Some((expires, entitlements)) = self.cached_entitlements().get(instance) {
+            if *expires > now {
+                return Ok(entitlements.clone());
+            }
+        }
+
+        let policy = match pool.get(Binding::Root).await {
+            Ok(db) => get_instance_policy(&db, instance).await,
+            Err(e) => Err(e),
+        };
+        let policy = policy.map_err(|e| {
+            tracing::warn!("Failed to read the entitlements of `{instance}`: {:?}", e);
+            ApiError::Unavailable(e)
+        })?;
+        let entitlements = policy.map(|policy| Entitlements::new(&self.settings, policy));
+        let expires = now + self.refresh_interval();
+        let mut cached = self.cached_entitlements();
+        if cached.len() >= MAX_CACHED_ENTITLEMENTS && !cached.contains_key(instance) {
+            cached.retain(|_, (expires, _)| *expires > now);
+        }
+        if cached.len() < MAX_CACHED_ENTITLEMENTS || cached.contains_key(instance) {
+            cached.insert(instance.into(), (expires, entitlements.clone()));
+        }
+        Ok(entitlements)
+    }
+
+    /// Drops the cached entitlements of an instance, so that changed overrides
+    /// and plans apply to the next request.
+    pub fn forget(&self, instance: &str) {
+        self.cached_entitlements().remove(instance);
+    }
+
+    /// Drops every cached entitlement, such as after a plan has changed.
+    pub fn forget_all(&self) {
+        self.cached_entitlements().clear();
+    }
+
+    /// Takes a token from the bucket of the instance and, when given, of the API
+    /// key, and counts the call towards the daily allowance of the instance.
+    /// Nothing is taken unless every limit admits the request.
+    pub fn check(
+        &self,
+        instance: &str,
+        api_key: Option<&str>,
+        entitlements: &Entitlements,
+    ) -> Result<(), RateLimited> {
+        let now = Instant::now();
+        let keys = std::iter::once((
+            BucketKey::Instance(instance.into()),
+            entitlements.instance,
+            "instance",
+        ))
+        .chain(api_key.map(|key| {
+            let key = BucketKey::ApiKey {
+                instance: instance.into(),
+                digest: Sha256::digest(key.as_bytes()).into(),
+            };
+            (key, entitlements.api_key, "api_key")
+        }))
+        .collect::<Vec<_>>();
+
+        let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned");
+        for (key, limit, name) in &keys {
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket::full(*limit, now));
+            bucket.refill(*limit, now);
+            if let Some(retry_after) = bucket.wait() {
+                return Err(RateLimited {
+                    limit: name,
+                    retry_after,
+                });
+            }
+        }
+
+        if let Some(max_calls) = entitlements.max_daily_api_calls {
+            let (today, until_tomorrow) = today();
+            let mut daily_calls = self.daily_calls.lock().expect("Rate limiter lock poisoned");
+            let (day, calls) = daily_calls.entry(instance.into()).or_insert((today, 0));
+            if *day != today {
+                *day = today;
+                *calls = 0;
+            }
+            if *calls >= max_calls {
+                return Err(RateLimited {
+                    limit: "daily_api_calls",
+                    retry_after: until_tomorrow,
+                });
+            }
+            *calls += 1;
+        }
+
+        for (key, ..) in &keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Periodically forgets full buckets, past days and expired entitlements,
+    /// which would otherwise accumulate for every host and API key ever seen.
+    pub fn spawn_sweep(self: &Arc<Self>, shutdown: Shutdown) {
+        let limiter = Arc::downgrade(self);
+        let period = self.refresh_interval();
+
+        actix_web::rt::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.triggered() => break,
+                }
+                let Some(limiter) = Weak::upgrade(&limiter) else {
+                    break;
+                };
+                limiter.sweep();
+            }
+        });
+    }
+
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .retain(|_, bucket| {
+                let limit = bucket.limit;
+                bucket.refill(limit, now);
+                bucket.tokens < f64::from(limit.burst)
+            });
+        let (today, _) = today();
+        self.daily_calls
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .retain(|_, (day, _)| *day == today);
+        self.cached_entitlements()
+            .retain(|_, (expires, _)| *expires > now);
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.settings.refresh_interval_secs.max(1))
+    }
+
+    fn cached_entitlements(
+        &self,
+    ) -> MutexGuard<'_, HashMap<String, (Instant, Option<Entitlements>)>> {
+        self.entitlements
+            .lock()
+            .expect("Rate limiter lock poisoned")
+    }
+}
+
+/// Returns the current UTC day, counted from the Unix epoch, and the time left until the next.
+fn today() -> (u64, Duration) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let day = now.as_secs() / SECONDS_PER_DAY;
+    let tomorrow = Duration::from_secs((day + 1) * SECONDS_PER_DAY);
+    (day, tomorrow.saturating_sub(now))
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    /// Adds the tokens accrued since the last refill. A changed limit applies
+    /// from now on.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.requests_per_second).min(f64::from(limit.burst));
+        self.limit = limit;
+        self.updated = now;
+    }
+
+    /// How long until a token is available, if none is now.
+    fn wait(&self) -> Option<Duration> {
+        (self.tokens < 1.0)
+            .then(|| Duration::from_secs_f64((1.0 - self.tokens) / self.limit.requests_per_second))
+    }
+}
@@ -1,24 +1,282 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand};
 use rush_data_server::{
+    audit::{details, Actor},
     configuration::{get_configuration, ApplicationSettings, Settings},
-    database::init_db,
+    database::{
+        auth::define_admin_user,
+        credentials::generate_password,
+        export::{export, import},
+        init_db,
+        instance_migrations::{migrate_instance, migrate_instances},
+        instances::{create_instance, delete_instance, list_instances, set_instance_state},
+        migrations::instance_migrations,
+        pool::{Binding, ConnectionPool},
+    },
+    model::{audit::AuditAction, instance::InstanceState},
+    recycle_bin::spawn_purge,
     run,
-    telemetry::init_telemetry,
+    shutdown::Shutdown,
+    telemetry::{init_telemetry, shutdown_telemetry},
+    tls::server_config,
 };
-use std::{io, net::TcpListener};
+use secrecy::{ExposeSecret, Secret};
+use serde_json::Map;
+use std::{fs, io, net::TcpListener, path::PathBuf, process, time::Duration};
+
+/// Runs and administers the Rush data server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Defaults to `serve`.
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Kept from before the subcommands, same as `config check`.
+    #[arg(long, hide = true)]
+    check_config: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Starts the HTTP server.
+    Serve,
+    /// Applies pending root and instance migrations, then exits.
+    Migrate,
+    /// Manages instances.
+    #[command(subcommand)]
+    Instance(InstanceCommand),
+    /// Manages database users.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Inspects the configuration.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Writes the schema and records of an instance as a SurrealQL script.
+    Export {
+        name: String,
+        /// Writes to stdout when not given.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replays a script written by `export` into an existing instance.
+    Import { name: String, input: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum InstanceCommand {
+    /// Creates and provisions an instance.
+    Create { name: String },
+    /// Lists every instance with its state.
+    List,
+    /// Deletes an instance together with all of its data.
+    Delete {
+        name: String,
+        /// Confirms that the instance data should be removed.
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Marks an instance as suspended. Running servers refuse its requests once
+    /// they refresh its entitlements, within `limits.refresh_interval_secs`.
+    Suspend { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum UserCommand {
+    /// Defines a database user with the owner role at the configured auth level.
+    CreateAdmin {
+        username: String,
+        /// A random password is generated and printed when not given.
+        #[arg(long, env = "RUSH_ADMIN_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Prints the effective configuration with secrets redacted, exiting with a
+    /// non-zero status if it is invalid.
+    Check,
+}
+
+fn check_config() -> ! {
+    match get_configuration() {
+        Ok(settings) => {
+            let settings =
+                serde_json::to_string_pretty(&settings).expect("Failed to serialize settings");
+            println!("{settings}");
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+}
 
 #[actix_web::main]
-async fn main() -> io::Result<()> {
-    init_telemetry()?;
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+    if cli.check_config || matches!(command, Command::Config(ConfigCommand::Check)) {
+        check_config();
+    }
+
+    let settings = get_configuration().context("Failed to read configuration")?;
+    match command {
+        Command::Serve => serve(settings).await?,
+        command => administer(settings, command).await?,
+    }
+    Ok(())
+}
+
+async fn serve(settings: Settings) -> anyhow::Result<()> {
     let Settings {
         database,
         application,
-    } = get_configuration().expect("Failed to read configuration.");
+        limits,
+        usage,
+        recycle_bin,
+        webhooks,
+        telemetry,
+    } = settings;
+    init_telemetry(&telemetry)?;
 
-    let ApplicationSettings { host, port } = application;
+    let ApplicationSettings {
+        host,
+        port,
+        admin_port,
+        tls,
+        shutdown_timeout_secs,
+    } = application;
     let address = format!("{host}:{port}");
 
-    let db = init_db(database).await.expect("Could not initialize db");
+    let shutdown = Shutdown::new(Duration::from_secs(shutdown_timeout_secs));
+    shutdown.trigger_on_signal();
+
+    let db = init_db(&database).await?;
+    let pool = ConnectionPool::new(&database, db)?;
 
+    let migration_pool = pool.clone();
+    let migration_shutdown = shutdown.clone();
+    actix_web::rt::spawn(async move {
+        let concurrency = database.instance_migration_concurrency;
+        if let Err(e) = migrate_instances(&migration_pool, concurrency, &migration_shutdown).await {
+            tracing::error!("Failed to migrate instances: {:?}", e);
+        }
+    });
+    spawn_purge(pool.clone(), recycle_bin, shutdown.clone());
+
+    let tls = tls
+        .map(|tls| server_config(&tls, &shutdown))
+        .transpose()
+        .map_err(io::Error::other)?;
     let listener = TcpListener::bind(address)?;
-    run(listener, db).await
+    let admin_listener = admin_port
+        .map(|admin_port| TcpListener::bind(format!("{host}:{admin_port}")))
+        .transpose()?;
+    let result = run(
+        listener,
+        admin_listener,
+        tls,
+        pool,
+        limits,
+        usage,
+        webhooks,
+        shutdown,
+    )
+    .await;
+
+    tracing::info!("Flushing telemetry");
+    shutdown_telemetry();
+    Ok(result?)
+}
+
+/// Runs a one-off command against the configured database.
+async fn administer(settings: Settings, command: Command) -> anyhow::Result<()> {
+    let Settings {
+        database,
+        telemetry,
+        ..
+    } = settings;
+    match &command {
+        Command::Migrate => init_telemetry(&telemetry)?,
+        Command::Instance(InstanceCommand::Delete { name, yes }) => {
+            anyhow::ensure!(yes, "Deleting `{name}` removes all of its data, pass --yes");
+        }
+        _ => {}
+    }
+
+    // Connecting applies pending root migrations.
+    let db = init_db(&database).await?;
+    let pool = ConnectionPool::new(&database, db)?;
+
+    match command {
+        Command::Serve | Command::Config(_) => unreachable!("Handled before connecting"),
+        Command::Migrate => {
+            let shutdown = Shutdown::new(Duration::ZERO);
+            migrate_instances(&pool, database.instance_migration_concurrency, &shutdown).await?;
+        }
+        Command::Instance(InstanceCommand::Create { name }) => {
+            let root = pool.get(Binding::Root).await?;
+            let actor = Actor::cli();
+            let event = actor.event(AuditAction::InstanceCreated, Map::new());
+            create_instance(&root, pool.cipher(), &name, &event).await?;
+            drop(root);
+            migrate_instance(&pool, &instance_migrations()?, &name).await?;
+            println!("Created instance `{name}`");
+        }
+        Command::Instance(InstanceCommand::List) => {
+            let root = pool.get(Binding::Root).await?;
+            for instance in list_instances(&root).await? {
+                println!("{}\t{}", instance.name, instance.state);
+            }
+        }
+        Command::Instance(InstanceCommand::Delete { name, .. }) => {
+            let root = pool.get(Binding::Root).await?;
+            let actor = Actor::cli();
+            let event = actor.event(AuditAction::InstanceDeleted, Map::new());
+            delete_instance(&root, &name, &event).await?;
+            pool.evict(&name);
+            println!("Deleted instance `{name}`");
+        }
+        Command::Instance(InstanceCommand::Suspend { name }) => {
+            let root = pool.get(Binding::Root).await?;
+            let actor = Actor::cli();
+            let event = actor.event(AuditAction::InstanceSuspended, Map::new());
+            set_instance_state(&root, &name, InstanceState::Suspended, &event).await?;
+            println!("Suspended instance `{name}`");
+        }
+        Command::User(UserCommand::CreateAdmin { username, password }) => {
+            let generated = password.is_none();
+            let password = password.map(Secret::new).unwrap_or_else(generate_password);
+            let root = pool.get(Binding::Root).await?;
+            let actor = Actor::cli();
+            let level = details("level", database.auth_level.to_string());
+            let event = actor.event(AuditAction::AdminUserCreated, level);
+            define_admin_user(&root, database.auth_level, &username, &password, &event).await?;
+            println!("Created {} user `{username}`", database.auth_level);
+            if generated {
+                println!("Password: {}", password.expose_secret());
+            }
+        }
+        Command::Export { name, output } => {
+            let db = pool.get(Binding::InstanceSchema(name)).await?;
+            let script = export(&db).await?;
+            match output {
+                Some(path) => fs::write(&path, script)
+                    .with_context(|| format!("Failed to write {}", path.display()))?,
+                None => print!("{script}"),
+            }
+        }
+        Command::Import { name, input } => {
+            let script = fs::read_to_string(&input)
+                .with_context(|| format!("Failed to read {}", input.display()))?;
+            let db = pool.get(Binding::InstanceSchema(name.clone())).await?;
+            import(&db, &script).await?;
+            println!("Imported {} into `{name}`", input.display());
+        }
+    }
+
+    pool.close();
+    Ok(())
 }
@@ -0,0 +1,115 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+
+pub use prometheus::TEXT_FORMAT as CONTENT_TYPE;
+
+use crate::database::pool::ConnectionPool;
+
+pub static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests handled",
+        &["method", "route", "status"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static HTTP_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time taken to handle HTTP requests",
+        &["method", "route", "status"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static INSTANCE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "instance_requests_total",
+        "Number of HTTP requests handled per instance",
+        &["instance"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static RATE_LIMITED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rate_limited_requests_total",
+        "Number of requests rejected by a rate limit",
+        &["limit"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static DB_QUERY_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "surrealdb_query_duration_seconds",
+        "Time taken by SurrealDB queries"
+    )
+    .expect("Failed to register metric")
+});
+
+pub static DB_QUERY_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "surrealdb_query_errors_total",
+        "Number of failed SurrealDB queries"
+    )
+    .expect("Failed to register metric")
+});
+
+pub static DB_POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "surrealdb_pool_connections",
+        "Number of pooled SurrealDB connections",
+        &["state"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static DB_POOL_MAX_CONNECTIONS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "surrealdb_pool_max_connections",
+        "Maximum number of pooled SurrealDB connections"
+    )
+    .expect("Failed to register metric")
+});
+
+pub static INSTANCES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "instances",
+        "Number of instances by lifecycle state",
+        &["state"]
+    )
+    .expect("Failed to register metric")
+});
+
+/// Refreshes the gauges that are sampled rather than updated as events happen.
+pub fn record_pool_status(pool: &ConnectionPool) {
+    let status = pool.status();
+    DB_POOL_MAX_CONNECTIONS.set(status.max as i64);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["active"])
+        .set(status.active as i64);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(status.idle as i64);
+}
+
+/// Sets the instance gauge to the given counts, dropping states that no longer occur.
+pub fn record_instance_counts(counts: &[(String, i64)]) {
+    INSTANCES.reset();
+    for (state, count) in counts {
+        INSTANCES.with_label_values(&[state]).add(*count);
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn encode() -> anyhow::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
@@ -0,0 +1,94 @@
+use super::request_id::RequestId;
+use crate::error::{ApiError, ErrorBody};
+use actix_web::{
+    body::{BoxBody, EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{self, HeaderValue},
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+/// Renders every error response as a JSON [`ErrorBody`] carrying the request id.
+/// Must be wrapped by `RequestIdProcessor`, which assigns the request id.
+pub struct JsonErrors;
+
+impl<S, B> Transform<S, ServiceRequest> for JsonErrors
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = JsonErrorsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(JsonErrorsMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct JsonErrorsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for JsonErrorsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+
+    type Error = Error;
+
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        async move {
+            let res = srv.call(req).await?;
+
+            let Some(error) = res.response().error() else {
+                return Ok(res.map_into_left_body());
+            };
+
+            let mut body = match error.as_error::<ApiError>() {
+                Some(error) => error.body(),
+                None => ErrorBody::from_status(res.status(), error.to_string()),
+            };
+            body.request_id = res
+                .request()
+                .extensions()
+                .get::<RequestId>()
+                .map(ToString::to_string);
+
+            let body = match serde_json::to_string(&body) {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::error!("Failed to serialize error body: {:?}", e);
+                    return Ok(res.map_into_left_body());
+                }
+            };
+
+            let (req, res) = res.into_parts();
+            let mut res = res.set_body(BoxBody::new(body));
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+
+            Ok(ServiceResponse::new(req, res).map_into_right_body())
+        }
+        .boxed_local()
+    }
+}
@@ -1 +1,6 @@
+pub mod json_errors;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod usage;
 pub mod virtual_hosting;
@@ -0,0 +1,119 @@
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web::Data,
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use sha2::{Digest, Sha256};
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::{
+    database::pool::ConnectionPool,
+    error::ApiError,
+    limits::Limiter,
+    metrics::RATE_LIMITED_REQUESTS,
+    model::instance::{InstanceName, InstanceState},
+};
+
+/// The header identifying the API key a request is made with.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Identifies an API key in stored usage without revealing it.
+pub fn api_key_digest(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+/// Rejects requests to suspended instances and those that exceed the rate
+/// limits of their instance, and makes the instance's
+/// [`Entitlements`](crate::limits::Entitlements) available to handlers. Requests to
+/// instances that do not exist are passed on without them. Must be wrapped by
+/// `VirtualHostProcessor`, which identifies the instance.
+pub struct RateLimiter;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimiterMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+
+    type Error = Error;
+
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        async move {
+            let instance = req.extensions().get::<InstanceName>().cloned();
+            let limiter = req.app_data::<Data<Limiter>>().cloned();
+            let pool = req.app_data::<Data<ConnectionPool>>().cloned();
+            let (Some(instance), Some(limiter), Some(pool)) = (instance, limiter, pool) else {
+                return srv.call(req).await.map(ServiceResponse::map_into_left_body);
+            };
+
+            let entitlements = match limiter.entitlements(&pool, &instance).await {
+                Ok(Some(entitlements)) => entitlements,
+                Ok(None) => {
+                    return srv.call(req).await.map(ServiceResponse::map_into_left_body);
+                }
+                Err(error) => return Ok(req.error_response(error).map_into_right_body()),
+            };
+            if entitlements.state == InstanceState::Suspended {
+                tracing::info!("Refused request to suspended instance `{instance}`");
+                let error = ApiError::Suspended(instance.to_string());
+                return Ok(req.error_response(error).map_into_right_body());
+            }
+
+            let api_key = req
+                .headers()
+                .get(API_KEY_HEADER)
+                .and_then(|key| key.to_str().ok());
+
+            if let Err(limited) = limiter.check(&instance, api_key, &entitlements) {
+                tracing::info!(
+                    "Rate limited request to `{instance}` by its {} limit",
+                    limited.limit
+                );
+                RATE_LIMITED_REQUESTS
+                    .with_label_values(&[limited.limit])
+                    .inc();
+                let error = ApiError::TooManyRequests {
+                    retry_after: limited.retry_after,
+                };
+                return Ok(req.error_response(error).map_into_right_body());
+            }
+
+            req.extensions_mut().insert(entitlements);
+            srv.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        .boxed_local()
+    }
+}
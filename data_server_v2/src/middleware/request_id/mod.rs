@@ -0,0 +1,124 @@
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use std::{
+    fmt::Display,
+    future::{ready, Ready},
+    rc::Rc,
+};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_LENGTH: usize = 128;
+
+/// Identifies a request across logs, error bodies and database queries.
+#[derive(Debug, Clone)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Accepts an id supplied by the caller if it is short and made of safe
+    /// characters, and generates a new one otherwise.
+    pub(crate) fn from_header(value: Option<&HeaderValue>) -> Self {
+        let supplied = value.and_then(|value| value.to_str().ok()).filter(|value| {
+            !value.is_empty()
+                && value.len() <= MAX_LENGTH
+                && value
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || "-_.".contains(char))
+        });
+
+        match supplied {
+            Some(value) => Self(value.into()),
+            None => Self(Uuid::new_v4().to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(|| RequestId::from_header(None));
+        ready(Ok(id))
+    }
+}
+
+/// Echoes the request id in the response. The id is taken from the request
+/// span, which `InstanceRootSpanBuilder` builds with it, or assigned here when
+/// there is none.
+pub struct RequestIdProcessor;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdProcessor
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+
+    type Error = Error;
+
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let assigned = req.extensions().get::<RequestId>().cloned();
+        let id = assigned.unwrap_or_else(|| {
+            let id = RequestId::from_header(req.headers().get(REQUEST_ID_HEADER));
+            req.extensions_mut().insert(id.clone());
+            id
+        });
+
+        async move {
+            let mut res = srv.call(req).await?;
+
+            if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+                res.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+
+            Ok(res)
+        }
+        .boxed_local()
+    }
+}
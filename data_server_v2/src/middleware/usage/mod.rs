@@ -0,0 +1,75 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web::Data,
+    Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use super::rate_limit::API_KEY_HEADER;
+use crate::{limits::Entitlements, model::instance::InstanceName, usage::UsageMeter};
+
+/// Meters the requests to an instance that are admitted by its rate limits.
+/// Must be wrapped by `RateLimiter`, and so by `VirtualHostProcessor`. Requests
+/// to hosts that name no instance carry no entitlements and are not metered,
+/// so that they cannot grow the meter.
+pub struct UsageMetering;
+
+impl<S, B> Transform<S, ServiceRequest> for UsageMetering
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = UsageMeteringMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(UsageMeteringMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct UsageMeteringMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for UsageMeteringMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+{
+    type Response = ServiceResponse<B>;
+
+    type Error = Error;
+
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        async move {
+            let instance = req
+                .extensions()
+                .get::<InstanceName>()
+                .filter(|_| req.extensions().contains::<Entitlements>())
+                .cloned();
+            if let (Some(instance), Some(meter)) = (instance, req.app_data::<Data<UsageMeter>>()) {
+                let api_key = req
+                    .headers()
+                    .get(API_KEY_HEADER)
+                    .and_then(|key| key.to_str().ok());
+                meter.record(&instance, api_key);
+            }
+            srv.call(req).await
+        }
+        .boxed_local()
+    }
+}
@@ -8,6 +8,7 @@ use std::{
     future::{ready, Ready},
     rc::Rc,
 };
+use tracing_actix_web::RootSpan;
 
 use crate::model::instance::InstanceName;
 
@@ -56,6 +57,9 @@ where
 
             if let Ok(instance) = instance {
                 tracing::debug!("Instance name found: {instance}");
+                if let Some(span) = req.extensions().get::<RootSpan>() {
+                    span.record("instance.name", instance.to_string());
+                }
                 req.extensions_mut().insert(instance);
             } else {
                 tracing::debug!("No instance name found");
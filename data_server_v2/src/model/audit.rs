@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// What an audit event records. Instance events are kept in the root database,
+/// object and record events in the database of their instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    InstanceCreated,
+    InstanceDeleted,
+    InstanceSuspended,
+    InstanceLimitsUpdated,
+    InstancePlanAssigned,
+    PlanUpdated,
+    AdminUserCreated,
+    ObjectCreated,
+    ObjectPublished,
+    ObjectDeleted,
+    ObjectRestored,
+    ObjectPurged,
+    RecordCreated,
+    RecordUpdated,
+    RecordDeleted,
+    RecordRestored,
+    RecordPurged,
+    WebhookCreated,
+    WebhookDeleted,
+}
+
+/// An entry of an audit log.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AuditEvent {
+    pub at: DateTime<Utc>,
+    pub action: AuditAction,
+    /// Who performed the action: `cli`, `system` or, for requests, `anonymous`.
+    pub actor: String,
+    pub request_id: Option<String>,
+    /// The name of the instance, plan, user or object acted on, or the
+    /// `object:id` of a record.
+    pub target: String,
+    /// Record events hold `changes`, mapping each changed field to its value
+    /// `before` and `after` the action.
+    #[serde(default)]
+    pub details: Map<String, Value>,
+}
+
+/// Filters an audit log. Events are listed newest first.
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<AuditAction>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// A page of audit events, and the offset of the next page if there is one.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub next_offset: Option<u32>,
+}
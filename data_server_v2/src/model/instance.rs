@@ -2,12 +2,52 @@ use std::{fmt::Display, ops::Deref};
 
 use serde::{Deserialize, Serialize};
 
+use crate::configuration::RateLimit;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Instance {
     pub name: String,
 }
 
-#[derive(Debug)]
+/// The lifecycle state of an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceState {
+    #[default]
+    Active,
+    Suspended,
+}
+
+impl Display for InstanceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceState::Active => write!(f, "active"),
+            InstanceState::Suspended => write!(f, "suspended"),
+        }
+    }
+}
+
+/// An instance as listed to operators.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstanceSummary {
+    pub name: String,
+    /// Instances created before states were recorded have none and are active.
+    #[serde(default)]
+    pub state: InstanceState,
+}
+
+/// Overrides of the configured limits, stored on the instance record. Limits
+/// left unset fall back to the configured defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceLimits {
+    pub instance: Option<RateLimit>,
+    pub api_key: Option<RateLimit>,
+    pub max_records: Option<u64>,
+    pub max_storage_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
 pub struct InstanceName(String);
 
 impl Deref for InstanceName {
@@ -34,7 +74,7 @@ impl TryFrom<&str> for InstanceName {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let mut period_count: u8 = 0;
         let mut instance_name = None;
-        for (idx, char) in value.chars().enumerate() {
+        for (idx, char) in value.char_indices() {
             if char == '.' {
                 if period_count == 0 {
                     instance_name = Some(InstanceName(value[0..idx].into()));
@@ -1 +1,8 @@
+pub mod audit;
 pub mod instance;
+pub mod object;
+pub mod plan;
+pub mod recycle_bin;
+pub mod usage;
+pub mod version;
+pub mod webhook;
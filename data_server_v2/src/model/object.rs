@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// An object definition, whose records are stored in a table of the same name
+/// once it is published.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ObjectTable {
+    pub name: String,
+    pub published: bool,
+    pub system: bool,
+    pub settings: Map<String, Value>,
+}
+
+/// An object in the recycle bin.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeletedObject {
+    pub name: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewObject {
+    pub name: String,
+    #[serde(default)]
+    pub settings: Map<String, Value>,
+}
+
+/// How many versions of its records an object keeps, read from the `history`
+/// entry of its settings. Every version is kept when no limit is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistorySettings {
+    /// Keeps at most this many versions of each record, and none when 0.
+    pub max_versions: Option<u64>,
+    /// Drops versions older than this, except the latest one.
+    pub max_age_days: Option<u64>,
+}
+
+impl HistorySettings {
+    pub fn from_settings(settings: &Map<String, Value>) -> serde_json::Result<Self> {
+        settings
+            .get("history")
+            .map_or(Ok(Self::default()), Self::deserialize)
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// A capability that plans can include.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// The resource API under `/api`.
+    Api,
+    /// Webhook subscriptions under `/webhooks`.
+    Webhooks,
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::Api => write!(f, "api"),
+            Feature::Webhooks => write!(f, "webhooks"),
+        }
+    }
+}
+
+/// A tier instances can be assigned to.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Plan {
+    pub name: String,
+    pub entitlements: PlanEntitlements,
+}
+
+/// What instances on a plan may use. Unset limits are unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanEntitlements {
+    pub max_users: Option<u64>,
+    pub max_objects: Option<u64>,
+    pub max_records: Option<u64>,
+    pub max_daily_api_calls: Option<u64>,
+    #[serde(default)]
+    pub features: Vec<Feature>,
+}
+
+/// The plan an instance is assigned to.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanAssignment {
+    /// Removes the plan when `None`, lifting its restrictions.
+    pub plan: Option<String>,
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::object::DeletedObject;
+
+/// A record in the recycle bin, whose `deleted_at` field is set.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeletedRecord {
+    pub object: String,
+    pub record: Value,
+}
+
+/// The deleted objects and records of an instance that can still be restored.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RecycleBin {
+    pub objects: Vec<DeletedObject>,
+    pub records: Vec<DeletedRecord>,
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The usage of an instance during one hour. Records and storage are sampled at
+/// the last aggregation within the hour.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HourlyUsage {
+    pub instance: String,
+    /// The start of the hour.
+    pub hour: DateTime<Utc>,
+    pub api_requests: u64,
+    pub records: u64,
+    pub storage_bytes: u64,
+    /// Identified by the distinct API keys that made requests, until instances
+    /// have users.
+    pub active_users: u64,
+}
+
+/// Selects usage from `from` up to, but excluding, `to`.
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub instance: Option<String>,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A snapshot of a record, taken whenever it is written.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RecordVersion {
+    /// Counts up from 1, the version the record was created with.
+    pub version: u64,
+    pub at: DateTime<Utc>,
+    /// Who wrote the version, as recorded in the audit log.
+    pub actor: String,
+    /// The fields of the record, without its id.
+    pub content: Map<String, Value>,
+}
+
+/// Selects two versions of a record to compare.
+#[derive(Debug, Deserialize)]
+pub struct VersionDiffQuery {
+    pub from: u64,
+    pub to: u64,
+}
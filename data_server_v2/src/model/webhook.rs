@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+
+/// Something that happens in an instance that webhooks can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    RecordCreated,
+    RecordUpdated,
+    RecordDeleted,
+    ObjectPublished,
+}
+
+impl Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::RecordCreated => write!(f, "record_created"),
+            WebhookEvent::RecordUpdated => write!(f, "record_updated"),
+            WebhookEvent::RecordDeleted => write!(f, "record_deleted"),
+            WebhookEvent::ObjectPublished => write!(f, "object_published"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWebhook {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    /// Limits the subscription to the events of one object.
+    #[serde(default)]
+    pub object: Option<String>,
+}
+
+/// A subscription of a URL to events of an instance.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub object: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A new webhook with the secret its deliveries are signed with, which is only
+/// ever returned when the webhook is created.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its next attempt.
+    Pending,
+    Delivered,
+    /// Given up on after the configured number of attempts.
+    Failed,
+}
+
+/// The outcome of one attempt to deliver an event.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeliveryAttempt {
+    pub at: DateTime<Utc>,
+    /// The status the receiver responded with, if it responded at all.
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// An event queued for a webhook, with the log of its delivery attempts.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub event: WebhookEvent,
+    pub object: String,
+    pub status: DeliveryStatus,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub data: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    pub limit: Option<u32>,
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Map;
+use std::time::Duration;
+use tokio::time::{interval_at, Instant};
+
+use crate::{
+    audit::Actor,
+    configuration::RecycleBinSettings,
+    database::{
+        instances::list_instances,
+        objects::{expired_objects, purge_object},
+        pool::{Binding, ConnectionPool},
+        records::{published_objects, purge_expired_records},
+    },
+    model::audit::AuditAction,
+    shutdown::Shutdown,
+};
+
+/// Purges the records and objects of every instance that have been in its
+/// recycle bin for longer than the configured retention, every configured
+/// interval until `shutdown` is triggered.
+pub fn spawn_purge(pool: ConnectionPool, settings: RecycleBinSettings, shutdown: Shutdown) {
+    let period = Duration::from_secs(settings.purge_interval_secs.max(1));
+    let retention = i64::try_from(settings.retention_days)
+        .ok()
+        .and_then(TimeDelta::try_days)
+        .unwrap_or(TimeDelta::MAX);
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = interval_at(Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let cutoff = Utc::now()
+                .checked_sub_signed(retention)
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            if let Err(e) = purge_expired(&pool, cutoff).await {
+                tracing::warn!("Failed to purge recycle bins: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Purges what every instance moved to its recycle bin before `cutoff`.
+#[tracing::instrument(name = "Purging recycle bins", skip(pool))]
+pub async fn purge_expired(pool: &ConnectionPool, cutoff: DateTime<Utc>) -> anyhow::Result<()> {
+    let instances = {
+        let db = pool.get(Binding::Root).await?;
+        list_instances(&db).await?
+    };
+
+    for instance in instances {
+        if let Err(e) = purge_instance(pool, &instance.name, cutoff).await {
+            tracing::warn!(
+                "Failed to purge the recycle bin of `{}`: {:?}",
+                instance.name,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn purge_instance(
+    pool: &ConnectionPool,
+    instance: &str,
+    cutoff: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    // Purging objects removes their tables, which only the root user may do.
+    let db = pool.get(Binding::InstanceSchema(instance.into())).await?;
+    let actor = Actor::system();
+
+    let event = actor.event(AuditAction::ObjectPurged, Map::new());
+    for object in expired_objects(&db, cutoff).await? {
+        purge_object(&db, &object, &event).await?;
+    }
+
+    let event = actor.event(AuditAction::RecordPurged, Map::new());
+    for object in published_objects(&db).await? {
+        purge_expired_records(&db, &object, cutoff, &event).await?;
+    }
+    Ok(())
+}
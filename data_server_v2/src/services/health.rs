@@ -0,0 +1,118 @@
+use std::{future::Future, time::Instant};
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+
+use crate::database::{
+    migrations::{pending_migrations, root_migrations},
+    pool::{Binding, ConnectionPool},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Up,
+    Down,
+}
+
+/// The outcome of a single readiness check.
+#[derive(Debug, Serialize)]
+pub struct ComponentStatus {
+    pub status: ComponentState,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Components {
+    pub database: ComponentStatus,
+    pub migrations: ComponentStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: ComponentState,
+    pub components: Components,
+}
+
+async fn check<F>(check: F) -> ComponentStatus
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    let start = Instant::now();
+    let result = check.await;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    match result {
+        Ok(()) => ComponentStatus {
+            status: ComponentState::Up,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => ComponentStatus {
+            status: ComponentState::Down,
+            latency_ms,
+            error: Some(format!("{e:#}")),
+        },
+    }
+}
+
+/// Reports that the process is up, without looking at any dependency.
+#[tracing::instrument(name = "Liveness check requested")]
+pub async fn live() -> HttpResponse {
+    tracing::debug!("Liveness check requested");
+    HttpResponse::Ok().finish()
+}
+
+/// Reports whether the server can serve traffic: the database must answer and
+/// every root migration must be applied.
+#[tracing::instrument(name = "Readiness check requested", skip(pool))]
+pub async fn ready(pool: web::Data<ConnectionPool>) -> HttpResponse {
+    let mut connection = None;
+    let database = check(async {
+        if pool.is_reconnecting() {
+            anyhow::bail!("The pool is reconnecting to the database");
+        }
+        let db = pool.get(Binding::Root).await?;
+        db.health().await?;
+        connection = Some(db);
+        Ok(())
+    })
+    .await;
+
+    let migrations = check(async {
+        let db = connection
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The database is unavailable"))?;
+        let migrations = root_migrations()?;
+        let pending = pending_migrations(db, &migrations).await?;
+        if !pending.is_empty() {
+            anyhow::bail!("{} root migrations are pending", pending.len());
+        }
+        Ok(())
+    })
+    .await;
+    drop(connection);
+
+    let status = if database.status == ComponentState::Up && migrations.status == ComponentState::Up
+    {
+        ComponentState::Up
+    } else {
+        tracing::warn!("Server is not ready");
+        ComponentState::Down
+    };
+
+    let readiness = Readiness {
+        status,
+        components: Components {
+            database,
+            migrations,
+        },
+    };
+
+    match status {
+        ComponentState::Up => HttpResponse::Ok().json(readiness),
+        ComponentState::Down => HttpResponse::ServiceUnavailable().json(readiness),
+    }
+}
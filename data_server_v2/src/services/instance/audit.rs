@@ -0,0 +1,16 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    audit::query_page, database::extractors::InstanceConnection, error::ApiError,
+    model::audit::AuditQuery,
+};
+
+/// Lists the object and record events of the instance, newest first.
+#[tracing::instrument(skip(db))]
+pub async fn list_events(
+    query: web::Query<AuditQuery>,
+    db: InstanceConnection,
+) -> Result<HttpResponse, ApiError> {
+    let page = query_page(&db, &query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
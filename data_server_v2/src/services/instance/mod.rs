@@ -2,11 +2,117 @@ use actix_web::{guard, web, HttpMessage, HttpRequest, HttpResponse};
 
 use crate::{guards::instance_filter::instance_filter, model::instance::InstanceName};
 
+use self::{
+    audit::list_events,
+    records::{create_record, delete_record, get_record, list_records, update_record},
+    recycle_bin::{list_recycle_bin, purge_object, purge_record, restore_object, restore_record},
+    schema::{create_object, delete_object, list_objects, publish_object},
+    versions::{diff_versions, list_versions, restore_version},
+    webhooks::{create_webhook, delete_webhook, list_deliveries, list_webhooks},
+};
+
+mod audit;
+mod records;
+mod recycle_bin;
+mod schema;
+mod versions;
+mod webhooks;
+
 pub fn instance_service(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/")
             .guard(guard::fn_guard(instance_filter))
             .route(web::get().to(instance_name)),
+    )
+    .service(
+        web::resource("/schema/objects")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_objects))
+            .route(web::post().to(create_object)),
+    )
+    .service(
+        web::resource("/schema/objects/{name}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::delete().to(delete_object)),
+    )
+    .service(
+        web::resource("/schema/objects/{name}/publish")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::post().to(publish_object)),
+    )
+    .service(
+        web::resource("/api/{object}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_records))
+            .route(web::post().to(create_record)),
+    )
+    .service(
+        web::resource("/api/{object}/{id}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(get_record))
+            .route(web::patch().to(update_record))
+            .route(web::delete().to(delete_record)),
+    )
+    .service(
+        web::resource("/api/{object}/{id}/versions")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_versions)),
+    )
+    .service(
+        web::resource("/api/{object}/{id}/versions/diff")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(diff_versions)),
+    )
+    .service(
+        web::resource("/api/{object}/{id}/versions/{version}/restore")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::post().to(restore_version)),
+    )
+    .service(
+        web::resource("/audit")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_events)),
+    )
+    .service(
+        web::resource("/recycle-bin")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_recycle_bin)),
+    )
+    .service(
+        web::resource("/recycle-bin/objects/{name}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::delete().to(purge_object)),
+    )
+    .service(
+        web::resource("/recycle-bin/objects/{name}/restore")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::post().to(restore_object)),
+    )
+    .service(
+        web::resource("/recycle-bin/records/{object}/{id}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::delete().to(purge_record)),
+    )
+    .service(
+        web::resource("/recycle-bin/records/{object}/{id}/restore")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::post().to(restore_record)),
+    )
+    .service(
+        web::resource("/webhooks")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_webhooks))
+            .route(web::post().to(create_webhook)),
+    )
+    .service(
+        web::resource("/webhooks/{id}")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::delete().to(delete_webhook)),
+    )
+    .service(
+        web::resource("/webhooks/{id}/deliveries")
+            .guard(guard::fn_guard(instance_filter))
+            .route(web::get().to(list_deliveries)),
     );
 }
 
@@ -0,0 +1,129 @@
+use actix_web::{web, HttpResponse};
+use serde_json::{Map, Value};
+
+use crate::{
+    audit::Actor,
+    database::{extractors::InstanceConnection, records, versions::NewVersion},
+    error::ApiError,
+    limits::Entitlements,
+    model::{audit::AuditAction, object::HistorySettings, plan::Feature},
+};
+
+/// Fails unless `object` is a published object table of the instance, and
+/// returns how much history it keeps of its records.
+pub(super) async fn published(
+    db: &InstanceConnection,
+    entitlements: &Entitlements,
+    object: &str,
+) -> Result<HistorySettings, ApiError> {
+    entitlements.require(Feature::Api)?;
+    let table = records::published_object(db, object)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No published object named `{object}`")))?;
+    Ok(
+        HistorySettings::from_settings(&table.settings).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Keeping all versions of `{object}`, its history settings are invalid: {e}"
+            );
+            HistorySettings::default()
+        }),
+    )
+}
+
+pub(super) fn record_not_found(object: &str, id: &str) -> ApiError {
+    ApiError::NotFound(format!("No `{object}` record with id `{id}`"))
+}
+
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn list_records(
+    object: web::Path<String>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    published(&db, &entitlements, &object).await?;
+    let records = records::list_records(&db, &object).await?;
+    Ok(HttpResponse::Ok().json(records))
+}
+
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn get_record(
+    path: web::Path<(String, String)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    let record = records::get_record(&db, &object, &id)
+        .await?
+        .ok_or_else(|| record_not_found(&object, &id))?;
+    Ok(HttpResponse::Ok().json(record))
+}
+
+#[tracing::instrument(skip(db, content, entitlements, actor))]
+pub async fn create_record(
+    object: web::Path<String>,
+    content: web::Json<Map<String, Value>>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let history = published(&db, &entitlements, &object).await?;
+    let version = NewVersion {
+        actor: &actor.name,
+        history,
+    };
+    let record = records::create_record(
+        &db,
+        &object,
+        content.into_inner(),
+        entitlements.storage_quota(),
+        &version,
+        &actor.event(AuditAction::RecordCreated, Map::new()),
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(record))
+}
+
+#[tracing::instrument(skip(db, content, entitlements, actor))]
+pub async fn update_record(
+    path: web::Path<(String, String)>,
+    content: web::Json<Map<String, Value>>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    let history = published(&db, &entitlements, &object).await?;
+    let version = NewVersion {
+        actor: &actor.name,
+        history,
+    };
+    let update = records::update_record(
+        &db,
+        &object,
+        &id,
+        content.into_inner(),
+        entitlements.storage_quota(),
+        &version,
+        &actor.event(AuditAction::RecordUpdated, Map::new()),
+    )
+    .await?
+    .ok_or_else(|| record_not_found(&object, &id))?;
+    Ok(HttpResponse::Ok().json(update.after))
+}
+
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn delete_record(
+    path: web::Path<(String, String)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    let event = actor.event(AuditAction::RecordDeleted, Map::new());
+    records::delete_record(&db, &object, &id, &event)
+        .await?
+        .ok_or_else(|| record_not_found(&object, &id))?;
+    Ok(HttpResponse::NoContent().finish())
+}
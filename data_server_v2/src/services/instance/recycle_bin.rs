@@ -0,0 +1,117 @@
+use actix_web::{web, HttpResponse};
+use serde_json::Map;
+
+use super::records::published;
+use crate::{
+    audit::Actor,
+    database::{
+        extractors::{InstanceConnection, InstanceSchemaConnection},
+        objects, records,
+    },
+    error::ApiError,
+    limits::Entitlements,
+    model::{
+        audit::AuditAction,
+        recycle_bin::{DeletedRecord, RecycleBin},
+    },
+};
+
+fn object_not_deleted(name: &str) -> ApiError {
+    ApiError::NotFound(format!("No object named `{name}` in the recycle bin"))
+}
+
+fn record_not_deleted(object: &str, id: &str) -> ApiError {
+    ApiError::NotFound(format!(
+        "No `{object}` record with id `{id}` in the recycle bin"
+    ))
+}
+
+/// Lists the deleted objects, and the deleted records of the objects that are
+/// not deleted themselves.
+#[tracing::instrument(skip(db))]
+pub async fn list_recycle_bin(db: InstanceConnection) -> Result<HttpResponse, ApiError> {
+    let objects = objects::list_deleted_objects(&db).await?;
+    let mut records = Vec::new();
+    for object in objects::list_objects(&db).await? {
+        if !object.published {
+            continue;
+        }
+        records.extend(
+            records::list_deleted_records(&db, &object.name)
+                .await?
+                .into_iter()
+                .map(|record| DeletedRecord {
+                    object: object.name.clone(),
+                    record,
+                }),
+        );
+    }
+    Ok(HttpResponse::Ok().json(RecycleBin { objects, records }))
+}
+
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn restore_object(
+    name: web::Path<String>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    // Objects in the recycle bin do not count towards the object limit, so it
+    // is checked again. Its records count towards the storage quotas again,
+    // which is not checked, as they never left the instance.
+    entitlements.check_objects(objects::count_objects(&db).await?)?;
+    let event = actor.event(AuditAction::ObjectRestored, Map::new());
+    let restored = objects::restore_object(&db, &name, &event)
+        .await?
+        .ok_or_else(|| object_not_deleted(&name))?;
+    Ok(HttpResponse::Ok().json(restored))
+}
+
+/// Deletes an object in the recycle bin for good, with all of its records.
+#[tracing::instrument(skip(db, actor))]
+pub async fn purge_object(
+    name: web::Path<String>,
+    db: InstanceSchemaConnection,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    objects::validate_object_name(&name).map_err(|_| object_not_deleted(&name))?;
+    let event = actor.event(AuditAction::ObjectPurged, Map::new());
+    if !objects::purge_object(&db, &name, &event).await? {
+        return Err(object_not_deleted(&name));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn restore_record(
+    path: web::Path<(String, String)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    let quota = entitlements.storage_quota();
+    let event = actor.event(AuditAction::RecordRestored, Map::new());
+    let record = records::restore_record(&db, &object, &id, quota, &event)
+        .await?
+        .ok_or_else(|| record_not_deleted(&object, &id))?;
+    Ok(HttpResponse::Ok().json(record))
+}
+
+/// Deletes a record in the recycle bin for good, with its history.
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn purge_record(
+    path: web::Path<(String, String)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    let event = actor.event(AuditAction::RecordPurged, Map::new());
+    records::purge_record(&db, &object, &id, &event)
+        .await?
+        .ok_or_else(|| record_not_deleted(&object, &id))?;
+    Ok(HttpResponse::NoContent().finish())
+}
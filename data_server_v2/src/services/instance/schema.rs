@@ -0,0 +1,88 @@
+use actix_web::{web, HttpResponse};
+use serde_json::{json, Map};
+
+use crate::{
+    audit::{details, Actor},
+    database::{
+        extractors::{InstanceConnection, InstanceSchemaConnection},
+        objects,
+    },
+    error::ApiError,
+    limits::Entitlements,
+    model::{
+        audit::AuditAction,
+        object::{HistorySettings, NewObject},
+    },
+};
+
+#[tracing::instrument(skip(db))]
+pub async fn list_objects(db: InstanceConnection) -> Result<HttpResponse, ApiError> {
+    let objects = objects::list_objects(&db).await?;
+    Ok(HttpResponse::Ok().json(objects))
+}
+
+#[tracing::instrument(skip(db, entitlements, actor), fields(name = %object.name))]
+pub async fn create_object(
+    object: web::Json<NewObject>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    objects::validate_object_name(&object.name)
+        .map_err(|e| ApiError::bad_request(e.to_string()))?;
+    HistorySettings::from_settings(&object.settings)
+        .map_err(|e| ApiError::bad_request(format!("`settings.history` is invalid: {e}")))?;
+    entitlements.check_objects(objects::count_objects(&db).await?)?;
+
+    let settings = details("settings", object.settings.clone());
+    let event = actor.event(AuditAction::ObjectCreated, settings);
+    let created = objects::create_object(&db, &object, &event)
+        .await
+        .map_err(|e| match ApiError::from(e) {
+            ApiError::Conflict { .. } => ApiError::Conflict {
+                message: format!("An object named `{}` already exists", object.name),
+                details: Some(json!({ "field": "name", "value": object.name })),
+            },
+            e => e,
+        })?;
+    Ok(HttpResponse::Created().json(created))
+}
+
+/// Defines the table of an object, so that the resource API accepts its records.
+#[tracing::instrument(skip(db, actor))]
+pub async fn publish_object(
+    name: web::Path<String>,
+    db: InstanceSchemaConnection,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    objects::validate_object_name(&name).map_err(|e| ApiError::bad_request(e.to_string()))?;
+    let event = actor.event(AuditAction::ObjectPublished, Map::new());
+    let published = objects::publish_object(&db, &name, &event)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No object named `{name}`")))?;
+    Ok(HttpResponse::Ok().json(published))
+}
+
+/// Moves an object and its records to the recycle bin. System objects cannot
+/// be deleted.
+#[tracing::instrument(skip(db, actor))]
+pub async fn delete_object(
+    name: web::Path<String>,
+    db: InstanceConnection,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let object = objects::get_object(&db, &name)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No object named `{name}`")))?;
+    if object.system {
+        return Err(ApiError::bad_request(format!(
+            "`{name}` is a system object and cannot be deleted"
+        )));
+    }
+
+    let event = actor.event(AuditAction::ObjectDeleted, Map::new());
+    objects::delete_object(&db, &name, &event)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No object named `{name}`")))?;
+    Ok(HttpResponse::NoContent().finish())
+}
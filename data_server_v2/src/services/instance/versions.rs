@@ -0,0 +1,91 @@
+use actix_web::{web, HttpResponse};
+use serde_json::{json, Map, Value};
+
+use super::records::{published, record_not_found};
+use crate::{
+    audit::{changes, Actor},
+    database::{
+        extractors::InstanceConnection,
+        records,
+        versions::{self, NewVersion},
+    },
+    error::ApiError,
+    limits::Entitlements,
+    model::{
+        audit::AuditAction,
+        version::{RecordVersion, VersionDiffQuery},
+    },
+};
+
+async fn get_version(
+    db: &InstanceConnection,
+    object: &str,
+    id: &str,
+    version: u64,
+) -> Result<RecordVersion, ApiError> {
+    versions::get_version(db, object, id, version)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No version {version} of `{object}:{id}`")))
+}
+
+/// Lists the kept versions of a record, newest first.
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn list_versions(
+    path: web::Path<(String, String)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    if records::get_record(&db, &object, &id).await?.is_none() {
+        return Err(record_not_found(&object, &id));
+    }
+    let versions = versions::list_versions(&db, &object, &id).await?;
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+/// Compares two versions of a record field by field.
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn diff_versions(
+    path: web::Path<(String, String)>,
+    query: web::Query<VersionDiffQuery>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id) = path.into_inner();
+    published(&db, &entitlements, &object).await?;
+    let from = get_version(&db, &object, &id, query.from).await?;
+    let to = get_version(&db, &object, &id, query.to).await?;
+
+    let mut diff = changes(
+        Some(&Value::Object(from.content)),
+        Some(&Value::Object(to.content)),
+    );
+    diff.insert("from".into(), json!(query.from));
+    diff.insert("to".into(), json!(query.to));
+    Ok(HttpResponse::Ok().json(diff))
+}
+
+/// Replaces a record with an earlier version of it, which becomes its newest
+/// version.
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn restore_version(
+    path: web::Path<(String, String, u64)>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    let (object, id, version) = path.into_inner();
+    let history = published(&db, &entitlements, &object).await?;
+    let content = get_version(&db, &object, &id, version).await?.content;
+    let version = NewVersion {
+        actor: &actor.name,
+        history,
+    };
+    let quota = entitlements.storage_quota();
+    let event = actor.event(AuditAction::RecordUpdated, Map::new());
+    let update = records::replace_record(&db, &object, &id, content, quota, &version, &event)
+        .await?
+        .ok_or_else(|| record_not_found(&object, &id))?;
+    Ok(HttpResponse::Ok().json(update.after))
+}
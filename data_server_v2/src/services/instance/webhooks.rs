@@ -0,0 +1,125 @@
+use actix_web::{web, HttpResponse};
+use rand::RngCore;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use serde_json::{Map, Value};
+
+use crate::{
+    audit::Actor,
+    database::{extractors::InstanceConnection, objects, pool::ConnectionPool, webhooks},
+    error::ApiError,
+    limits::Entitlements,
+    model::{
+        audit::AuditAction,
+        plan::Feature,
+        webhook::{CreatedWebhook, DeliveryQuery, NewWebhook},
+    },
+    webhooks::Receivers,
+};
+
+const DEFAULT_DELIVERY_PAGE_SIZE: u32 = 50;
+const MAX_DELIVERY_PAGE_SIZE: u32 = 200;
+
+fn webhook_not_found(id: &str) -> ApiError {
+    ApiError::NotFound(format!("No webhook with id `{id}`"))
+}
+
+/// Receivers must be plain HTTP(S) endpoints on public addresses.
+async fn validate_webhook(webhook: &NewWebhook, receivers: &Receivers) -> Result<(), ApiError> {
+    let url = Url::parse(&webhook.url)
+        .map_err(|e| ApiError::bad_request(format!("`url` is invalid: {e}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiError::bad_request("`url` must be an http or https URL"));
+    }
+    receivers
+        .check(&url)
+        .await
+        .map_err(|e| ApiError::bad_request(format!("`url` is not allowed: {e}")))?;
+    if webhook.events.is_empty() {
+        return Err(ApiError::bad_request("`events` must not be empty"));
+    }
+    if let Some(object) = &webhook.object {
+        objects::validate_object_name(object).map_err(|e| ApiError::bad_request(e.to_string()))?;
+    }
+    Ok(())
+}
+
+fn generate_secret() -> Secret<String> {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    Secret::new(hex::encode(secret))
+}
+
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn list_webhooks(
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    entitlements.require(Feature::Webhooks)?;
+    let webhooks = webhooks::list_webhooks(&db).await?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+/// Subscribes a URL to events of the instance. The response holds the secret
+/// deliveries are signed with, which cannot be read again later.
+#[tracing::instrument(skip(db, pool, entitlements, actor, receivers))]
+pub async fn create_webhook(
+    webhook: web::Json<NewWebhook>,
+    db: InstanceConnection,
+    pool: web::Data<ConnectionPool>,
+    entitlements: Entitlements,
+    actor: Actor,
+    receivers: web::Data<Receivers>,
+) -> Result<HttpResponse, ApiError> {
+    entitlements.require(Feature::Webhooks)?;
+    validate_webhook(&webhook, &receivers).await?;
+
+    let secret = generate_secret();
+    let details = match serde_json::to_value(&*webhook) {
+        Ok(Value::Object(details)) => details,
+        _ => Map::new(),
+    };
+    let event = actor.event(AuditAction::WebhookCreated, details);
+    let created = webhooks::create_webhook(&db, pool.cipher(), &webhook, &secret, &event).await?;
+    Ok(HttpResponse::Created().json(CreatedWebhook {
+        webhook: created,
+        secret: secret.expose_secret().clone(),
+    }))
+}
+
+/// Deletes a webhook, dropping its pending deliveries and delivery log.
+#[tracing::instrument(skip(db, entitlements, actor))]
+pub async fn delete_webhook(
+    id: web::Path<String>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    entitlements.require(Feature::Webhooks)?;
+    let event = actor.event(AuditAction::WebhookDeleted, Map::new());
+    if !webhooks::delete_webhook(&db, &id, &event).await? {
+        return Err(webhook_not_found(&id));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Lists the deliveries of a webhook, newest first, with the outcome of each
+/// attempt made.
+#[tracing::instrument(skip(db, entitlements))]
+pub async fn list_deliveries(
+    id: web::Path<String>,
+    query: web::Query<DeliveryQuery>,
+    db: InstanceConnection,
+    entitlements: Entitlements,
+) -> Result<HttpResponse, ApiError> {
+    entitlements.require(Feature::Webhooks)?;
+    if webhooks::get_webhook(&db, &id).await?.is_none() {
+        return Err(webhook_not_found(&id));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERY_PAGE_SIZE)
+        .min(MAX_DELIVERY_PAGE_SIZE);
+    let deliveries = webhooks::list_deliveries(&db, &id, query.status, limit).await?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
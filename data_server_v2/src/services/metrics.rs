@@ -0,0 +1,60 @@
+use actix_web::{
+    guard::{self, fn_guard},
+    web, HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    database::{extractors::RootConnection, pool::ConnectionPool, traced_query},
+    error::ApiError,
+    guards::instance_filter::instance_filter,
+    metrics,
+};
+
+#[derive(Debug, Deserialize)]
+struct InstanceCount {
+    state: Option<String>,
+    count: i64,
+}
+
+pub fn metrics_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/metrics")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(export_metrics)),
+    );
+}
+
+/// Counts instances by lifecycle state. Instances without a state are active.
+async fn count_instances(db: &RootConnection) -> anyhow::Result<Vec<(String, i64)>> {
+    let statement = "SELECT state, count() AS count FROM instance GROUP BY state";
+    let counts: Vec<InstanceCount> = traced_query(statement, db.query(statement))
+        .await?
+        .take(0)?;
+
+    Ok(counts
+        .into_iter()
+        .map(|count| {
+            let state = count.state.unwrap_or_else(|| "active".into());
+            (state, count.count)
+        })
+        .collect())
+}
+
+#[tracing::instrument(name = "Metrics requested", skip(db, pool))]
+async fn export_metrics(
+    db: RootConnection,
+    pool: web::Data<ConnectionPool>,
+) -> Result<HttpResponse, ApiError> {
+    match count_instances(&db).await {
+        Ok(counts) => metrics::record_instance_counts(&counts),
+        Err(e) => tracing::warn!("Failed to count instances: {:?}", e),
+    }
+    drop(db);
+
+    metrics::record_pool_status(&pool);
+
+    Ok(HttpResponse::Ok()
+        .content_type(metrics::CONTENT_TYPE)
+        .body(metrics::encode()?))
+}
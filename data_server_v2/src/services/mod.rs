@@ -1,6 +1,8 @@
 use actix_web::HttpResponse;
 
+pub mod health;
 pub mod instance;
+pub mod metrics;
 pub mod root;
 
 #[tracing::instrument(name = "Health check requested")]
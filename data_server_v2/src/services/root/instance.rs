@@ -1,40 +1,134 @@
-use crate::model::instance::Instance;
+use crate::{
+    audit::{details, Actor},
+    database::{
+        credentials::validate_instance_name, extractors::RootConnection,
+        instance_migrations::migrate_instance, instances, migrations::instance_migrations,
+        pool::ConnectionPool, traced_query,
+    },
+    error::ApiError,
+    limits::Limiter,
+    model::{
+        audit::AuditAction,
+        instance::{Instance, InstanceLimits},
+    },
+};
 use actix_web::{web, HttpResponse};
-use surrealdb::{engine::any::Any, Error, Surreal};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map};
+
+#[derive(Debug, Deserialize)]
+pub struct AvailabilityQuery {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Availability {
+    name: String,
+    available: bool,
+    reason: Option<String>,
+}
 
 #[tracing::instrument(
-    skip(db),
+    skip(db, pool, limiter, actor),
     fields(
     name = %instance.name,
     )
     )]
 pub async fn create_instance(
     instance: web::Json<Instance>,
-    db: web::Data<Surreal<Any>>,
-) -> HttpResponse {
+    db: RootConnection,
+    pool: web::Data<ConnectionPool>,
+    limiter: web::Data<Limiter>,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
     tracing::trace!("Reached create_instance route handler");
-    let resp = match create_instance_db(instance, db).await {
-        Ok(instance) => HttpResponse::Ok().json(instance),
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    };
+    validate_instance_name(&instance.name).map_err(|e| ApiError::bad_request(e.to_string()))?;
+    let name = instance.name.clone();
+    let instance = create_instance_db(instance, db, pool, actor).await?;
+    // Requests made before the instance existed may have been cached as unknown.
+    limiter.forget(&name);
     tracing::trace!("Handler exited");
-    resp
+    Ok(HttpResponse::Ok().json(instance))
 }
 
-#[tracing::instrument(skip(db))]
+#[tracing::instrument(skip(db, pool, actor))]
 async fn create_instance_db(
     instance: web::Json<Instance>,
-    db: web::Data<Surreal<Any>>,
-) -> Result<Vec<Instance>, Error> {
-    tracing::info!("Attempting to saving new instance to the db");
-    let instance = db
-        .create::<Vec<Instance>>("instance")
-        .content(instance)
+    db: RootConnection,
+    pool: web::Data<ConnectionPool>,
+    actor: Actor,
+) -> Result<Vec<Instance>, ApiError> {
+    let name = instance.into_inner().name;
+    let event = actor.event(AuditAction::InstanceCreated, Map::new());
+    let instance = instances::create_instance(&db, pool.cipher(), &name, &event)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to persist instance to db: {:?}", e);
-            e
+            tracing::error!("Failed to create instance: {:?}", e);
+            match ApiError::from(e) {
+                ApiError::Conflict { .. } => ApiError::Conflict {
+                    message: format!("An instance named `{name}` already exists"),
+                    details: Some(json!({ "field": "name", "value": name })),
+                },
+                e => e,
+            }
         })?;
+    // The pool needs a root connection to look up the new instance's credentials.
+    drop(db);
+
+    tracing::info!("Applying instance migrations");
+    if let Err(e) = migrate_instance(&pool, &instance_migrations()?, &name).await {
+        // The failure is recorded and reported by the migration status endpoint.
+        tracing::warn!("Instance created with pending migrations: {:?}", e);
+    }
+
     tracing::info!("Success");
-    Ok(instance)
+    Ok(vec![instance])
+}
+
+/// Reports whether an instance name is valid and not taken yet.
+#[tracing::instrument(skip(db))]
+pub async fn instance_availability(
+    query: web::Query<AvailabilityQuery>,
+    db: RootConnection,
+) -> Result<HttpResponse, ApiError> {
+    let name = query.into_inner().name;
+
+    let reason = if let Err(e) = validate_instance_name(&name) {
+        Some(e.to_string())
+    } else {
+        let statement = "SELECT VALUE name FROM instance WHERE name = $name LIMIT 1";
+        let taken: Vec<String> = traced_query(statement, db.query(statement).bind(("name", &name)))
+            .await?
+            .take(0)?;
+        (!taken.is_empty()).then(|| format!("An instance named `{name}` already exists"))
+    };
+
+    Ok(HttpResponse::Ok().json(Availability {
+        available: reason.is_none(),
+        name,
+        reason,
+    }))
+}
+
+/// Replaces the limit overrides of an instance, which apply from its next request.
+#[tracing::instrument(skip(db, limiter, actor))]
+pub async fn update_instance_limits(
+    name: web::Path<String>,
+    limits: web::Json<InstanceLimits>,
+    db: RootConnection,
+    limiter: web::Data<Limiter>,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    if instances::get_instance_limits(&db, &name).await?.is_none() {
+        return Err(ApiError::NotFound(format!(
+            "No instance named `{name}` exists"
+        )));
+    }
+
+    let details = details("limits", serde_json::to_value(&*limits).unwrap_or_default());
+    let event = actor.event(AuditAction::InstanceLimitsUpdated, details);
+    instances::set_instance_limits(&db, &name, &limits, &event).await?;
+    limiter.forget(&name);
+    let limits = limits.into_inner();
+    Ok(HttpResponse::Ok().json(limits))
 }
@@ -0,0 +1,12 @@
+use crate::{
+    database::{extractors::RootConnection, instance_migrations::instance_migration_statuses},
+    error::ApiError,
+};
+use actix_web::HttpResponse;
+
+#[tracing::instrument(skip(db))]
+pub async fn instance_migration_status(db: RootConnection) -> Result<HttpResponse, ApiError> {
+    tracing::trace!("Reached instance_migration_status route handler");
+    let statuses = instance_migration_statuses(&db).await?;
+    Ok(HttpResponse::Ok().json(statuses))
+}
@@ -1,17 +1,72 @@
 use crate::guards::instance_filter::instance_filter;
 
-use self::instance::create_instance;
+use self::{
+    audit::list_events,
+    instance::{create_instance, instance_availability, update_instance_limits},
+    migrations::instance_migration_status,
+    plans::{assign_plan, list_plans, put_plan},
+    usage::{export_usage, list_usage},
+};
 use actix_web::{
     guard::{self, fn_guard},
     web,
 };
 
+mod audit;
 mod instance;
+mod migrations;
+mod plans;
+mod usage;
 
 pub fn root_service(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/instance")
             .guard(guard::Not(fn_guard(instance_filter)))
             .route(web::post().to(create_instance)),
+    )
+    .service(
+        web::resource("/instance/availability")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(instance_availability)),
+    )
+    .service(
+        web::resource("/instance/migrations")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(instance_migration_status)),
+    )
+    .service(
+        web::resource("/instance/{name}/limits")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::put().to(update_instance_limits)),
+    )
+    .service(
+        web::resource("/instance/{name}/plan")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::put().to(assign_plan)),
+    )
+    .service(
+        web::resource("/plan")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(list_plans)),
+    )
+    .service(
+        web::resource("/plan/{name}")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::put().to(put_plan)),
+    )
+    .service(
+        web::resource("/usage")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(list_usage)),
+    )
+    .service(
+        web::resource("/usage/export")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(export_usage)),
+    )
+    .service(
+        web::resource("/audit")
+            .guard(guard::Not(fn_guard(instance_filter)))
+            .route(web::get().to(list_events)),
     );
 }
@@ -0,0 +1,83 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    audit::{details, Actor},
+    database::{extractors::RootConnection, instances, plans},
+    error::ApiError,
+    limits::Limiter,
+    model::{
+        audit::AuditAction,
+        plan::{PlanAssignment, PlanEntitlements},
+    },
+};
+
+/// Plan names appear in error messages and URLs, so they are kept simple.
+fn validate_plan_name(name: &str) -> Result<(), ApiError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::bad_request(
+            "Plan names may only contain lowercase letters, digits and hyphens",
+        ))
+    }
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn list_plans(db: RootConnection) -> Result<HttpResponse, ApiError> {
+    let plans = plans::list_plans(&db).await?;
+    Ok(HttpResponse::Ok().json(plans))
+}
+
+/// Creates a plan or replaces its entitlements, which apply to the instances
+/// on it from their next request.
+#[tracing::instrument(skip(db, limiter, actor))]
+pub async fn put_plan(
+    name: web::Path<String>,
+    entitlements: web::Json<PlanEntitlements>,
+    db: RootConnection,
+    limiter: web::Data<Limiter>,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    validate_plan_name(&name)?;
+    let details = details(
+        "entitlements",
+        serde_json::to_value(&*entitlements).unwrap_or_default(),
+    );
+    let event = actor.event(AuditAction::PlanUpdated, details);
+    let plan = plans::put_plan(&db, &name, &entitlements, &event).await?;
+    limiter.forget_all();
+    Ok(HttpResponse::Ok().json(plan))
+}
+
+#[tracing::instrument(skip(db, limiter, actor))]
+pub async fn assign_plan(
+    name: web::Path<String>,
+    assignment: web::Json<PlanAssignment>,
+    db: RootConnection,
+    limiter: web::Data<Limiter>,
+    actor: Actor,
+) -> Result<HttpResponse, ApiError> {
+    if instances::get_instance_limits(&db, &name).await?.is_none() {
+        return Err(ApiError::NotFound(format!(
+            "No instance named `{name}` exists"
+        )));
+    }
+    if let Some(plan) = &assignment.plan {
+        if plans::get_plan(&db, plan).await?.is_none() {
+            return Err(ApiError::NotFound(format!("No plan named `{plan}` exists")));
+        }
+    }
+
+    let event = actor.event(
+        AuditAction::InstancePlanAssigned,
+        details("plan", assignment.plan.clone()),
+    );
+    plans::set_instance_plan(&db, &name, assignment.plan.as_deref(), &event).await?;
+    limiter.forget(&name);
+    Ok(HttpResponse::Ok().json(assignment.into_inner()))
+}
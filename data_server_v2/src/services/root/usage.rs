@@ -0,0 +1,73 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use chrono::SecondsFormat;
+use std::{borrow::Cow, fmt::Write};
+
+use crate::{
+    database::{extractors::RootConnection, usage},
+    error::ApiError,
+    model::usage::{HourlyUsage, UsageQuery},
+};
+
+async fn query_usage(
+    db: &RootConnection,
+    query: &UsageQuery,
+) -> Result<Vec<HourlyUsage>, ApiError> {
+    if query.from >= query.to {
+        return Err(ApiError::bad_request("`from` must be before `to`"));
+    }
+    Ok(usage::query_usage(db, query).await?)
+}
+
+/// Quotes a CSV field that contains a separator, a quote or a line break,
+/// doubling its quotes, as RFC 4180 has it.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Lists the hourly usage of every instance, or of one, within a date range.
+#[tracing::instrument(skip(db))]
+pub async fn list_usage(
+    query: web::Query<UsageQuery>,
+    db: RootConnection,
+) -> Result<HttpResponse, ApiError> {
+    let usage = query_usage(&db, &query).await?;
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+/// Exports the same usage as [`list_usage`] as CSV, for billing.
+#[tracing::instrument(skip(db))]
+pub async fn export_usage(
+    query: web::Query<UsageQuery>,
+    db: RootConnection,
+) -> Result<HttpResponse, ApiError> {
+    let usage = query_usage(&db, &query).await?;
+
+    let mut csv = String::from("instance,hour,api_requests,records,storage_bytes,active_users\n");
+    for row in usage {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            csv_field(&row.instance),
+            row.hour.to_rfc3339_opts(SecondsFormat::Secs, true),
+            row.api_requests,
+            row.records,
+            row.storage_bytes,
+            row.active_users
+        );
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("usage.csv".into())],
+        })
+        .body(csv))
+}
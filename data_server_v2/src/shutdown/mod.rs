@@ -0,0 +1,70 @@
+use std::{io, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// Coordinates a graceful shutdown. Once triggered the servers stop accepting
+/// connections and drain in-flight requests for up to `timeout`, while background
+/// jobs stop at their next opportunity.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    token: CancellationToken,
+    timeout: Duration,
+}
+
+impl Shutdown {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            token: CancellationToken::new(),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn trigger(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Resolves once the shutdown has been triggered.
+    pub async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Triggers the shutdown on SIGTERM or SIGINT.
+    pub fn trigger_on_signal(&self) {
+        let shutdown = self.clone();
+        actix_web::rt::spawn(async move {
+            match signal().await {
+                Ok(signal) => tracing::info!("Received {signal}, shutting down"),
+                Err(e) => {
+                    tracing::error!("Failed to listen for shutdown signals: {:?}", e);
+                    return;
+                }
+            }
+            shutdown.trigger();
+        });
+    }
+}
+
+#[cfg(unix)]
+async fn signal() -> io::Result<&'static str> {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = terminate.recv() => Ok("SIGTERM"),
+        _ = interrupt.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> io::Result<&'static str> {
+    actix_web::rt::signal::ctrl_c().await?;
+    Ok("Ctrl-C")
+}
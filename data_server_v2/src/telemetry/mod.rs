@@ -1,43 +1,140 @@
 use opentelemetry::{
-    global, runtime::TokioCurrentThread, sdk::propagation::TraceContextPropagator,
+    global,
+    runtime::TokioCurrentThread,
+    sdk::{propagation::TraceContextPropagator, trace, Resource},
+    trace::TraceError,
+    KeyValue,
 };
-use std::{
-    fs::{self, File},
-    io::{self, stdout},
-    sync::Arc,
-};
-use tracing_bunyan_formatter::JsonStorageLayer;
+use opentelemetry_otlp::WithExportConfig;
+use std::io::{self, stdout};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, EnvFilter, Layer, Registry};
 
-pub fn init_telemetry() -> io::Result<()> {
+use crate::configuration::{
+    FileLogSettings, LogFormat, LogRotation, OtlpProtocol, TelemetrySettings, TraceExporter,
+};
+
+mod root_span;
+
+pub use root_span::InstanceRootSpanBuilder;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+pub fn init_telemetry(settings: &TelemetrySettings) -> io::Result<()> {
     LogTracer::init().expect("Failed to set logger");
     let app_name = env!("CARGO_PKG_NAME");
 
     global::set_text_map_propagator(TraceContextPropagator::new());
-    let tracer = opentelemetry_jaeger::new_agent_pipeline()
-        .with_service_name(app_name)
-        .install_batch(TokioCurrentThread)
-        .expect("Failed to install OpenTelemetry tracer.");
-
-    fs::create_dir_all("./logs").expect("Could not create directory"); // TODO: make log file a configuration options
-    let stdout_log = tracing_subscriber::fmt::layer()
-        .pretty()
-        .with_writer(stdout); // TODO: make stdout log a configuration option
-    let file = File::create("logs/debug.log")?;
-
-    let debug_log = tracing_subscriber::fmt::layer().with_writer(Arc::new(file));
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("info"));
-    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
-
-    let subscriber = Registry::default()
-        .with(env_filter)
-        .with(telemetry)
-        .with(JsonStorageLayer)
-        .with(stdout_log.and_then(debug_log));
+
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+
+    if let Some(tracer) = install_tracer(&settings.exporter, app_name).map_err(io::Error::other)? {
+        layers.push(Box::new(tracing_opentelemetry::layer().with_tracer(tracer)));
+    }
+
+    match settings.format {
+        LogFormat::Pretty => {
+            layers.push(Box::new(
+                tracing_subscriber::fmt::layer()
+                    .pretty()
+                    .with_writer(stdout),
+            ));
+        }
+        LogFormat::Compact => {
+            layers.push(Box::new(
+                tracing_subscriber::fmt::layer()
+                    .compact()
+                    .with_writer(stdout),
+            ));
+        }
+        LogFormat::Bunyan => {
+            layers.push(Box::new(JsonStorageLayer));
+            layers.push(Box::new(BunyanFormattingLayer::new(
+                app_name.into(),
+                stdout,
+            )));
+        }
+    }
+
+    if let Some(file) = &settings.file {
+        layers.push(Box::new(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(file_appender(file)?),
+        ));
+    }
+
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.level));
+
+    let subscriber = Registry::default().with(layers).with(env_filter);
 
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to install `tracing` subscriber.");
 
     Ok(())
 }
+
+/// Flushes spans still waiting in the batch exporter.
+pub fn shutdown_telemetry() {
+    global::shutdown_tracer_provider();
+}
+
+fn install_tracer(
+    exporter: &TraceExporter,
+    app_name: &str,
+) -> Result<Option<trace::Tracer>, TraceError> {
+    let tracer = match exporter {
+        TraceExporter::None => return Ok(None),
+        TraceExporter::Jaeger { endpoint } => {
+            let pipeline = opentelemetry_jaeger::new_agent_pipeline().with_service_name(app_name);
+            match endpoint {
+                Some(endpoint) => pipeline.with_endpoint(endpoint),
+                None => pipeline,
+            }
+            .install_batch(TokioCurrentThread)?
+        }
+        TraceExporter::Otlp { endpoint, protocol } => {
+            let pipeline = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", app_name.to_string()),
+                ])));
+            match protocol {
+                OtlpProtocol::Grpc => pipeline
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .tonic()
+                            .with_endpoint(endpoint),
+                    )
+                    .install_batch(TokioCurrentThread)?,
+                OtlpProtocol::Http => pipeline
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .http()
+                            .with_endpoint(endpoint),
+                    )
+                    .install_batch(TokioCurrentThread)?,
+            }
+        }
+    };
+
+    Ok(Some(tracer))
+}
+
+fn file_appender(settings: &FileLogSettings) -> io::Result<RollingFileAppender> {
+    let rotation = match settings.rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(&settings.file_name)
+        .build(&settings.directory)
+        .map_err(io::Error::other)
+}
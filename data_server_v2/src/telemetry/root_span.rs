@@ -0,0 +1,91 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header::HeaderMap, Version},
+    Error, HttpMessage,
+};
+use opentelemetry::{global, propagation::Extractor, trace::TraceContextExt};
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::middleware::request_id::{RequestId, REQUEST_ID_HEADER};
+
+/// Builds the request span with the same fields as `tracing_actix_web::root_span!`,
+/// continuing any incoming W3C trace context, with an `instance.name` attribute
+/// that the virtual host middleware fills in.
+///
+/// The macro would set `request_id` to an id of its own, so the span is built
+/// here with the id accepted from or assigned to `X-Request-Id` instead. The id
+/// is stored on the request for `RequestIdProcessor` to echo.
+pub struct InstanceRootSpanBuilder;
+
+impl RootSpanBuilder for InstanceRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let request_id = RequestId::from_header(request.headers().get(REQUEST_ID_HEADER));
+        request.extensions_mut().insert(request_id.clone());
+
+        let connection_info = request.connection_info();
+        let method = request.method().as_str();
+        let route = request.match_pattern().unwrap_or_else(|| "default".into());
+        let span = tracing::info_span!(
+            "HTTP request",
+            http.method = %method,
+            http.route = %route,
+            http.flavor = %http_flavor(request.version()),
+            http.scheme = %connection_info.scheme(),
+            http.host = %connection_info.host(),
+            http.client_ip = %connection_info.realip_remote_addr().unwrap_or(""),
+            http.user_agent = %request
+                .headers()
+                .get("User-Agent")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or(""),
+            http.target = %request.uri().path_and_query().map_or("", |p| p.as_str()),
+            http.status_code = tracing::field::Empty,
+            otel.name = %format!("{method} {route}"),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            trace_id = tracing::field::Empty,
+            request_id = %request_id,
+            exception.message = tracing::field::Empty,
+            exception.details = tracing::field::Empty,
+            instance.name = tracing::field::Empty,
+        );
+        drop(connection_info);
+
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderCarrier(request.headers()))
+        });
+        span.set_parent(parent);
+        let trace_id = span.context().span().span_context().trace_id();
+        span.record("trace_id", tracing::field::display(trace_id));
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+fn http_flavor(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2.0",
+        Version::HTTP_3 => "3.0",
+        _ => "1.1",
+    }
+}
+
+struct HeaderCarrier<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderCarrier<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
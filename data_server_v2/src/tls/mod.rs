@@ -0,0 +1,232 @@
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    version::{TLS12, TLS13},
+    Certificate, PrivateKey, ServerConfig, SupportedProtocolVersion,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+use tokio::time::interval;
+
+use crate::{
+    configuration::{TlsSettings, TlsVersion},
+    shutdown::Shutdown,
+};
+
+/// Errors that prevent certificates from being loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("Failed to read `{}`", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("No certificate found in `{}`", .0.display())]
+    NoCertificate(PathBuf),
+    #[error("No private key found in `{}`", .0.display())]
+    NoPrivateKey(PathBuf),
+    #[error("The private key in `{}` is not supported", path.display())]
+    UnsupportedKey {
+        path: PathBuf,
+        #[source]
+        source: sign::SignError,
+    },
+    #[error("Failed to configure TLS")]
+    Config(#[from] rustls::Error),
+}
+
+/// Builds the rustls configuration for the public listener and watches the
+/// certificate files for changes until `shutdown` is triggered.
+pub fn server_config(
+    settings: &TlsSettings,
+    shutdown: &Shutdown,
+) -> Result<ServerConfig, TlsError> {
+    let store = Arc::new(CertificateStore::load(settings.clone())?);
+    store.spawn_reload(shutdown.clone());
+
+    let versions: &[&SupportedProtocolVersion] = match settings.min_version {
+        TlsVersion::Tls12 => &[&TLS13, &TLS12],
+        TlsVersion::Tls13 => &[&TLS13],
+    };
+
+    Ok(ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)?
+        .with_no_client_auth()
+        .with_cert_resolver(store))
+}
+
+/// Selects a certificate by the server name the client asked for, falling back
+/// to the default certificate.
+struct CertificateStore {
+    settings: TlsSettings,
+    certificates: RwLock<Certificates>,
+}
+
+struct Certificates {
+    default: Arc<CertifiedKey>,
+    domains: HashMap<String, Arc<CertifiedKey>>,
+    modified: Option<SystemTime>,
+}
+
+impl CertificateStore {
+    fn load(settings: TlsSettings) -> Result<Self, TlsError> {
+        let certificates = Certificates::load(&settings)?;
+        Ok(Self {
+            settings,
+            certificates: RwLock::new(certificates),
+        })
+    }
+
+    fn spawn_reload(self: &Arc<Self>, shutdown: Shutdown) {
+        let store = Arc::downgrade(self);
+        let period = Duration::from_secs(self.settings.reload_interval_secs.max(1));
+
+        actix_web::rt::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.triggered() => break,
+                }
+                let Some(store) = Weak::upgrade(&store) else {
+                    break;
+                };
+                store.reload();
+            }
+        });
+    }
+
+    /// Reloads every certificate once any of the files has changed. The current
+    /// certificates are kept if the new ones cannot be loaded.
+    fn reload(&self) {
+        let modified = last_modified(&self.settings);
+        if modified == self.read().modified {
+            return;
+        }
+
+        match Certificates::load(&self.settings) {
+            Ok(certificates) => {
+                tracing::info!("Reloaded TLS certificates");
+                *self
+                    .certificates
+                    .write()
+                    .expect("Certificate store lock poisoned") = certificates;
+            }
+            Err(e) => tracing::error!("Failed to reload TLS certificates: {:?}", e),
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Certificates> {
+        self.certificates
+            .read()
+            .expect("Certificate store lock poisoned")
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let certificates = self.read();
+        let Some(name) = client_hello.server_name() else {
+            return Some(certificates.default.clone());
+        };
+
+        let name = name.to_lowercase();
+        let wildcard = name
+            .split_once('.')
+            .map(|(_, parent)| format!("*.{parent}"));
+
+        certificates
+            .domains
+            .get(&name)
+            .or_else(|| wildcard.and_then(|wildcard| certificates.domains.get(&wildcard)))
+            .or(Some(&certificates.default))
+            .cloned()
+    }
+}
+
+impl Certificates {
+    fn load(settings: &TlsSettings) -> Result<Self, TlsError> {
+        // Taken before reading, so that a change made while loading triggers another reload.
+        let modified = last_modified(settings);
+        let default = load_certified_key(&settings.cert_path, &settings.key_path)?;
+        let domains = settings
+            .certificates
+            .iter()
+            .map(|certificate| {
+                let key = load_certified_key(&certificate.cert_path, &certificate.key_path)?;
+                Ok((certificate.domain.to_lowercase(), key))
+            })
+            .collect::<Result<_, TlsError>>()?;
+
+        Ok(Self {
+            default,
+            domains,
+            modified,
+        })
+    }
+}
+
+fn last_modified(settings: &TlsSettings) -> Option<SystemTime> {
+    let paths = [&settings.cert_path, &settings.key_path].into_iter().chain(
+        settings
+            .certificates
+            .iter()
+            .flat_map(|certificate| [&certificate.cert_path, &certificate.key_path]),
+    );
+
+    paths
+        .filter_map(|path| path.metadata().and_then(|m| m.modified()).ok())
+        .max()
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<Arc<CertifiedKey>, TlsError> {
+    let chain: Vec<Certificate> = rustls_pemfile::certs(&mut open(cert_path)?)
+        .map_err(|source| TlsError::Io {
+            path: cert_path.into(),
+            source,
+        })?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if chain.is_empty() {
+        return Err(TlsError::NoCertificate(cert_path.into()));
+    }
+
+    let key = rustls_pemfile::read_all(&mut open(key_path)?)
+        .map_err(|source| TlsError::Io {
+            path: key_path.into(),
+            source,
+        })?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.into()))?;
+    let key = sign::any_supported_type(&key).map_err(|source| TlsError::UnsupportedKey {
+        path: key_path.into(),
+        source,
+    })?;
+
+    Ok(Arc::new(CertifiedKey::new(chain, key)))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| TlsError::Io {
+            path: path.into(),
+            source,
+        })
+}
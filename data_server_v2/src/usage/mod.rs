@@ -0,0 +1,170 @@
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+use tokio::time::{interval_at, Instant};
+
+use crate::{
+    configuration::UsageSettings,
+    database::{
+        instances::list_instances,
+        pool::{Binding, ConnectionPool},
+        records::{storage_usage, StorageUsage},
+        usage::record_usage,
+    },
+    middleware::rate_limit::api_key_digest,
+    shutdown::Shutdown,
+};
+
+type UsageKey = (String, DateTime<Utc>);
+
+/// Requests metered since usage was last written.
+#[derive(Debug, Default)]
+struct PendingUsage {
+    requests: u64,
+    /// Digests of the API keys the requests were made with.
+    api_keys: HashSet<String>,
+}
+
+/// Meters requests to instances in memory and periodically adds them to the
+/// hourly usage in the root database, along with a sample of each instance's
+/// storage.
+pub struct UsageMeter {
+    settings: UsageSettings,
+    pending: Mutex<HashMap<UsageKey, PendingUsage>>,
+}
+
+impl UsageMeter {
+    pub fn new(settings: UsageSettings) -> Self {
+        Self {
+            settings,
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Counts a request to `instance`, made with `api_key` if it has one.
+    pub fn record(&self, instance: &str, api_key: Option<&str>) {
+        let mut pending = self.pending();
+        let usage = pending
+            .entry((instance.into(), current_hour()))
+            .or_default();
+        usage.requests += 1;
+        if let Some(api_key) = api_key {
+            usage.api_keys.insert(api_key_digest(api_key));
+        }
+    }
+
+    /// Writes the metered requests and the current storage of every instance.
+    /// Requests that could not be written are kept for the next attempt.
+    #[tracing::instrument(name = "Aggregating usage", skip_all)]
+    pub async fn aggregate(&self, pool: &ConnectionPool) -> anyhow::Result<()> {
+        let hour = current_hour();
+        let instances = {
+            let db = pool.get(Binding::Root).await?;
+            list_instances(&db).await?
+        };
+
+        let mut storage = HashMap::new();
+        for instance in instances {
+            let usage = match pool.get(Binding::Instance(instance.name.clone())).await {
+                Ok(db) => storage_usage(&db).await,
+                Err(e) => Err(e),
+            };
+            match usage {
+                Ok(usage) => {
+                    storage.insert(instance.name, usage);
+                }
+                Err(e) => tracing::warn!(
+                    "Failed to measure the storage of `{}`: {:?}",
+                    instance.name,
+                    e
+                ),
+            }
+        }
+
+        let mut pending = std::mem::take(&mut *self.pending()).into_iter();
+        let result = self.write(pool, hour, &mut pending, storage).await;
+        if result.is_err() {
+            self.restore(pending);
+        }
+        result
+    }
+
+    async fn write(
+        &self,
+        pool: &ConnectionPool,
+        hour: DateTime<Utc>,
+        pending: &mut impl Iterator<Item = (UsageKey, PendingUsage)>,
+        mut storage: HashMap<String, StorageUsage>,
+    ) -> anyhow::Result<()> {
+        let db = pool.get(Binding::Root).await?;
+        for ((instance, requests_hour), usage) in pending.by_ref() {
+            let sample = if requests_hour == hour {
+                storage.remove(&instance)
+            } else {
+                None
+            };
+            let api_keys = usage.api_keys.iter().cloned().collect();
+            if let Err(e) = record_usage(
+                &db,
+                &instance,
+                requests_hour,
+                usage.requests,
+                api_keys,
+                sample,
+            )
+            .await
+            {
+                self.restore([((instance, requests_hour), usage)]);
+                return Err(e);
+            }
+        }
+        for (instance, sample) in storage {
+            record_usage(&db, &instance, hour, 0, Vec::new(), Some(sample)).await?;
+        }
+        Ok(())
+    }
+
+    fn restore(&self, usage: impl IntoIterator<Item = (UsageKey, PendingUsage)>) {
+        let mut pending = self.pending();
+        for (key, usage) in usage {
+            let restored = pending.entry(key).or_default();
+            restored.requests += usage.requests;
+            restored.api_keys.extend(usage.api_keys);
+        }
+    }
+
+    /// Aggregates usage every configured interval until `shutdown` is triggered.
+    /// Usage metered after the last run is left to the caller to aggregate.
+    pub fn spawn_aggregation(self: &Arc<Self>, pool: ConnectionPool, shutdown: Shutdown) {
+        let meter = Arc::downgrade(self);
+        let period = Duration::from_secs(self.settings.aggregation_interval_secs.max(1));
+
+        actix_web::rt::spawn(async move {
+            let mut ticker = interval_at(Instant::now() + period, period);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = shutdown.triggered() => break,
+                }
+                let Some(meter) = Weak::upgrade(&meter) else {
+                    break;
+                };
+                if let Err(e) = meter.aggregate(&pool).await {
+                    tracing::warn!("Failed to aggregate usage: {:?}", e);
+                }
+            }
+        });
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<UsageKey, PendingUsage>> {
+        self.pending.lock().expect("Usage meter lock poisoned")
+    }
+}
+
+fn current_hour() -> DateTime<Utc> {
+    let now = Utc::now();
+    now.duration_trunc(TimeDelta::hours(1)).unwrap_or(now)
+}
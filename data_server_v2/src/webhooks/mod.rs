@@ -0,0 +1,253 @@
+use chrono::{TimeDelta, Utc};
+use futures_util::{stream, StreamExt};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, redirect::Policy, Client, Url};
+use secrecy::ExposeSecret;
+use serde_json::json;
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+use tokio::time::{interval_at, Instant};
+use uuid::Uuid;
+
+use crate::{
+    configuration::{RetrySettings, WebhookSettings},
+    database::{
+        credentials::SecretCipher,
+        instances::list_instances,
+        pool::{Binding, ConnectionPool},
+        webhooks::{claim_due_deliveries, record_attempt, DueDelivery},
+    },
+    model::{
+        instance::InstanceState,
+        webhook::{DeliveryAttempt, DeliveryStatus},
+    },
+    shutdown::Shutdown,
+};
+
+mod receivers;
+
+pub use receivers::{NotPublic, Receivers};
+
+pub const EVENT_HEADER: &str = "X-Rush-Event";
+pub const DELIVERY_HEADER: &str = "X-Rush-Delivery";
+pub const TIMESTAMP_HEADER: &str = "X-Rush-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Rush-Signature";
+
+/// How many due deliveries of an instance are attempted per dispatch.
+const DISPATCH_BATCH: u32 = 100;
+
+/// Signs a delivery, so that receivers can check it was sent by this server
+/// with the secret of their webhook, and when.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// How long to wait after the given number of failed attempts.
+fn backoff(retry: &RetrySettings, failures: u32) -> Duration {
+    let factor = 2u64.saturating_pow(failures.saturating_sub(1));
+    Duration::from_millis(
+        retry
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(retry.max_backoff_ms),
+    )
+}
+
+/// Attempts the due deliveries of every instance, every configured interval
+/// until `shutdown` is triggered.
+pub fn spawn_dispatch(pool: ConnectionPool, settings: WebhookSettings, shutdown: Shutdown) {
+    let period = Duration::from_secs(settings.dispatch_interval_secs.max(1));
+    // Tells the deliveries this server claimed from those of other replicas.
+    let claimant = Uuid::new_v4().to_string();
+    let receivers = Receivers::new(&settings.allowed_hosts);
+    // Redirects are not followed, as they could lead anywhere, and proxies are
+    // not used, as they would resolve receivers without the check for private addresses.
+    let client = match Client::builder()
+        .timeout(Duration::from_secs(settings.timeout_secs))
+        .dns_resolver(Arc::new(receivers.clone()))
+        .redirect(Policy::none())
+        .no_proxy()
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create the webhook client, webhooks are disabled: {e}");
+            return;
+        }
+    };
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = interval_at(Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let dispatched =
+                dispatch_pending(&pool, &client, &receivers, &settings, &claimant).await;
+            if let Err(e) = dispatched {
+                tracing::warn!("Failed to dispatch webhook deliveries: {:?}", e);
+            }
+        }
+    });
+}
+
+/// How long a dispatcher may take over a batch of deliveries before another
+/// can claim them: a timeout for every round of concurrent deliveries, and one more.
+fn claim_lease(settings: &WebhookSettings) -> Duration {
+    let concurrency = u32::try_from(settings.delivery_concurrency.max(1)).unwrap_or(u32::MAX);
+    let rounds = DISPATCH_BATCH.div_ceil(concurrency) + 1;
+    Duration::from_secs(settings.timeout_secs.saturating_mul(rounds.into()))
+}
+
+/// Claims and attempts the due deliveries of every active instance, one
+/// instance at a time and up to `delivery_concurrency` deliveries at a time.
+#[tracing::instrument(name = "Dispatching webhooks", skip_all)]
+pub async fn dispatch_pending(
+    pool: &ConnectionPool,
+    client: &Client,
+    receivers: &Receivers,
+    settings: &WebhookSettings,
+    claimant: &str,
+) -> anyhow::Result<()> {
+    let instances = {
+        let db = pool.get(Binding::Root).await?;
+        list_instances(&db).await?
+    };
+
+    for instance in instances {
+        if instance.state != InstanceState::Active {
+            continue;
+        }
+        let dispatched =
+            dispatch_instance(pool, client, receivers, settings, claimant, &instance.name).await;
+        if let Err(e) = dispatched {
+            tracing::warn!(
+                "Failed to dispatch the webhooks of `{}`: {:?}",
+                instance.name,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn dispatch_instance(
+    pool: &ConnectionPool,
+    client: &Client,
+    receivers: &Receivers,
+    settings: &WebhookSettings,
+    claimant: &str,
+    instance: &str,
+) -> anyhow::Result<()> {
+    let binding = Binding::Instance(instance.into());
+    // The connection is not held while receivers are waited for.
+    let due = {
+        let db = pool.get(binding.clone()).await?;
+        claim_due_deliveries(&db, claimant, claim_lease(settings), DISPATCH_BATCH).await?
+    };
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let attempts: Vec<_> = stream::iter(due)
+        .map(|delivery| async move {
+            let attempt = deliver(client, receivers, pool.cipher(), instance, &delivery).await;
+            (delivery, attempt)
+        })
+        .buffer_unordered(settings.delivery_concurrency.max(1))
+        .collect()
+        .await;
+
+    let retry = &settings.retry;
+    let db = pool.get(binding).await?;
+    for (delivery, attempt) in attempts {
+        let failures = delivery.attempts + 1;
+        let (status, next_attempt_at) = match attempt.status_code {
+            Some(code) if (200..300).contains(&code) => (DeliveryStatus::Delivered, None),
+            _ if failures >= retry.max_attempts => (DeliveryStatus::Failed, None),
+            _ => {
+                let backoff = TimeDelta::from_std(backoff(retry, failures))?;
+                (DeliveryStatus::Pending, Some(attempt.at + backoff))
+            }
+        };
+        record_attempt(
+            &db,
+            claimant,
+            &delivery.id,
+            &attempt,
+            status,
+            next_attempt_at,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Posts a delivery to its webhook, signed with the webhook's secret.
+async fn deliver(
+    client: &Client,
+    receivers: &Receivers,
+    cipher: &SecretCipher,
+    instance: &str,
+    delivery: &DueDelivery,
+) -> DeliveryAttempt {
+    let at = Utc::now();
+    let failed = |error: String| DeliveryAttempt {
+        at,
+        status_code: None,
+        error: Some(error),
+    };
+    let url = match Url::parse(&delivery.url) {
+        Ok(url) => url,
+        Err(e) => return failed(e.to_string()),
+    };
+    // The client resolves host names through `receivers`, but connects to IP
+    // addresses directly.
+    if let Err(e) = receivers.check_ip(&url) {
+        return failed(e.to_string());
+    }
+
+    let body = json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "instance": instance,
+        "object": delivery.object,
+        "occurred_at": delivery.created_at,
+        "data": delivery.data,
+    })
+    .to_string();
+    let secret = match cipher.decrypt(&delivery.secret) {
+        Ok(secret) => secret,
+        Err(e) => return failed(e.to_string()),
+    };
+    let timestamp = at.timestamp();
+    let signature = sign(secret.expose_secret(), timestamp, body.as_bytes());
+
+    let response = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.to_string())
+        .header(DELIVERY_HEADER, &delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            DeliveryAttempt {
+                at,
+                status_code: Some(status.as_u16()),
+                error: (!status.is_success()).then(|| format!("The receiver responded {status}")),
+            }
+        }
+        Err(e) => failed(e.to_string()),
+    }
+}
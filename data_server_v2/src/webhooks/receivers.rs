@@ -0,0 +1,127 @@
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    Url,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::net::lookup_host;
+
+/// A host that is or resolves to an address deliveries may not be sent to.
+#[derive(Debug, Error)]
+#[error("`{host}` resolves to {ip}, which is not a public address")]
+pub struct NotPublic {
+    pub host: String,
+    pub ip: IpAddr,
+}
+
+/// Where webhook deliveries may be sent: to public addresses, and to the hosts
+/// allowed in the settings whatever they resolve to.
+///
+/// Receivers are checked when webhooks are registered and again on every
+/// delivery, as the client resolves host names through [`Resolve`] and only
+/// connects to the addresses checked, so that a host cannot be pointed at the
+/// server's own network after its webhook was registered.
+#[derive(Debug, Clone)]
+pub struct Receivers {
+    allowed_hosts: Arc<[String]>,
+}
+
+impl Receivers {
+    pub fn new(allowed_hosts: &[String]) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts.into(),
+        }
+    }
+
+    /// Fails if the host of `url` is or resolves to an address deliveries may
+    /// not be sent to. Hosts that do not resolve yet pass, as every delivery
+    /// checks them again.
+    pub async fn check(&self, url: &Url) -> Result<(), NotPublic> {
+        let host = url.host_str().unwrap_or_default();
+        if ip_address(host).is_some() {
+            return self.check_ip(url);
+        }
+        match lookup_host((host, 0)).await {
+            Ok(addrs) => self.check_addrs(host, addrs),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Fails if the host of `url` is an IP address deliveries may not be sent
+    /// to. The client connects to those without resolving them.
+    pub fn check_ip(&self, url: &Url) -> Result<(), NotPublic> {
+        let host = url.host_str().unwrap_or_default();
+        match ip_address(host) {
+            Some(ip) => self.check_addrs(host, [SocketAddr::new(ip, 0)]),
+            None => Ok(()),
+        }
+    }
+
+    /// `host` is written as in URLs, with IPv6 addresses in brackets.
+    fn check_addrs(
+        &self,
+        host: &str,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+    ) -> Result<(), NotPublic> {
+        if self.allowed_hosts.iter().any(|allowed| allowed == host) {
+            return Ok(());
+        }
+        match addrs.into_iter().find(|addr| !is_public(addr.ip())) {
+            Some(addr) => Err(NotPublic {
+                host: host.into(),
+                ip: addr.ip(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Resolve for Receivers {
+    fn resolve(&self, name: Name) -> Resolving {
+        let receivers = self.clone();
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = lookup_host((host, 0)).await?.collect();
+            receivers.check_addrs(host, addrs.iter().copied())?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Reads the IP address in a host as written in URLs.
+fn ip_address(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// Loopback, private, link-local and unspecified addresses belong to the
+/// server's own host or network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // The shared address space of carrier-grade NAT, 100.64.0.0/10.
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
@@ -0,0 +1,248 @@
+use std::{env, process::Command};
+
+use rush_data_server::{
+    audit::Actor,
+    configuration::{get_configuration, AuthLevel},
+    database::{
+        audit::NewEvent,
+        auth::define_admin_user,
+        export::{export, import},
+        init_db,
+        instance_migrations::migrate_instance,
+        instances::{create_instance, delete_instance, list_instances, set_instance_state},
+        migrations::instance_migrations,
+        pool::{Binding, ConnectionPool},
+    },
+    model::{audit::AuditAction, instance::InstanceState},
+};
+use secrecy::Secret;
+use serde_json::{Map, Value};
+
+async fn connect() -> ConnectionPool {
+    env::set_var("APP_ENVIRONMENT", "test");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let db = init_db(&settings.database)
+        .await
+        .expect("Could not initialize db");
+    ConnectionPool::new(&settings.database, db).expect("Failed to create pool")
+}
+
+fn event(actor: &Actor, action: AuditAction) -> NewEvent<'_> {
+    actor.event(action, Map::new())
+}
+
+async fn create(pool: &ConnectionPool, name: &str) {
+    let root = pool.get(Binding::Root).await.unwrap();
+    let actor = Actor::cli();
+    create_instance(
+        &root,
+        pool.cipher(),
+        name,
+        &event(&actor, AuditAction::InstanceCreated),
+    )
+    .await
+    .expect("Failed to create instance");
+    drop(root);
+    migrate_instance(pool, &instance_migrations().unwrap(), name)
+        .await
+        .expect("Failed to migrate instance");
+}
+
+#[actix_web::test]
+async fn instances_can_be_listed_suspended_and_deleted() {
+    let pool = connect().await;
+    create(&pool, "first").await;
+    create(&pool, "second").await;
+
+    let root = pool.get(Binding::Root).await.unwrap();
+    let actor = Actor::cli();
+    let suspended = event(&actor, AuditAction::InstanceSuspended);
+    let deleted = event(&actor, AuditAction::InstanceDeleted);
+    set_instance_state(&root, "second", InstanceState::Suspended, &suspended)
+        .await
+        .expect("Failed to suspend instance");
+    let instances = list_instances(&root).await.unwrap();
+    let listed: Vec<_> = instances
+        .iter()
+        .map(|instance| (instance.name.as_str(), instance.state))
+        .collect();
+    assert_eq!(
+        vec![
+            ("first", InstanceState::Active),
+            ("second", InstanceState::Suspended)
+        ],
+        listed
+    );
+
+    delete_instance(&root, "first", &deleted)
+        .await
+        .expect("Failed to delete instance");
+    let instances = list_instances(&root).await.unwrap();
+    assert_eq!(1, instances.len());
+    let credentials: Vec<Value> = root
+        .query("SELECT * FROM instance_credential WHERE instance_name = 'first'")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert!(credentials.is_empty());
+
+    assert!(delete_instance(&root, "first", &deleted).await.is_err());
+    assert!(
+        set_instance_state(&root, "missing", InstanceState::Suspended, &suspended)
+            .await
+            .is_err()
+    );
+}
+
+#[actix_web::test]
+async fn exported_instances_can_be_imported() {
+    let pool = connect().await;
+    create(&pool, "source").await;
+    create(&pool, "target").await;
+
+    let source = pool
+        .get(Binding::InstanceSchema("source".into()))
+        .await
+        .unwrap();
+    source
+        .query(
+            "CREATE object_table:users SET name = 'users';
+            CREATE object_field:email SET name = 'email', settings = { required: true };
+            RELATE object_table:users->has_field->object_field:email;",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    let script = export(&source).await.expect("Failed to export instance");
+    drop(source);
+    assert!(!script.contains("DEFINE TABLE migration"));
+
+    let target = pool
+        .get(Binding::InstanceSchema("target".into()))
+        .await
+        .unwrap();
+    import(&target, &script)
+        .await
+        .expect("Failed to import instance");
+
+    let mut response = target
+        .query(
+            "SELECT VALUE settings.required FROM object_field:email;
+            SELECT VALUE out.name FROM has_field WHERE in = object_table:users;",
+        )
+        .await
+        .unwrap();
+    let required: Vec<bool> = response.take(0).unwrap();
+    let fields: Vec<String> = response.take(1).unwrap();
+    assert_eq!(vec![true], required);
+    assert_eq!(vec!["email".to_string()], fields);
+}
+
+#[actix_web::test]
+async fn the_instance_user_can_only_append_to_the_audit_log() {
+    let pool = connect().await;
+    create(&pool, "acme").await;
+    let db = pool.get(Binding::Instance("acme".into())).await.unwrap();
+    db.query("CREATE audit_event SET action = 'object_created', actor = 'cli', target = 'users'")
+        .await
+        .unwrap()
+        .check()
+        .expect("Failed to append an audit event");
+
+    // Writes that table permissions deny change nothing, without an error.
+    for statement in [
+        "DELETE audit_event",
+        "UPDATE audit_event SET actor = 'someone else'",
+    ] {
+        db.query(statement).await.unwrap().check().unwrap();
+    }
+    let actors: Vec<String> = db
+        .query("SELECT VALUE actor FROM audit_event")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(vec!["cli".to_string()], actors);
+
+    for statement in [
+        "REMOVE EVENT append_only ON TABLE audit_event",
+        "DEFINE TABLE audit_event SCHEMAFULL PERMISSIONS FULL",
+        "REMOVE TABLE audit_event",
+    ] {
+        let result = db.query(statement).await.unwrap().check();
+        assert!(result.is_err(), "`{statement}` succeeded");
+    }
+}
+
+#[actix_web::test]
+async fn admin_users_need_safe_names_and_passwords() {
+    let pool = connect().await;
+    let root = pool.get(Binding::Root).await.unwrap();
+    let actor = Actor::cli();
+    let created = event(&actor, AuditAction::AdminUserCreated);
+
+    define_admin_user(
+        &root,
+        AuthLevel::Root,
+        "operator",
+        &Secret::new("s3cret".into()),
+        &created,
+    )
+    .await
+    .expect("Failed to define admin user");
+
+    let password = Secret::new("x".into());
+    let invalid_name = define_admin_user(&root, AuthLevel::Root, "a b", &password, &created).await;
+    assert!(invalid_name.is_err());
+    let password = Secret::new("it's".into());
+    let invalid_password =
+        define_admin_user(&root, AuthLevel::Root, "operator", &password, &created).await;
+    assert!(invalid_password.is_err());
+}
+
+#[test]
+fn the_cli_lists_instances() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .args(["instance", "list"])
+        .env("APP_ENVIRONMENT", "test")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn the_cli_requires_confirmation_to_delete_instances() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .args(["instance", "delete", "sample"])
+        .env("APP_ENVIRONMENT", "test")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+}
+
+#[test]
+fn the_server_exits_with_the_reason_it_cannot_reach_the_database() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .arg("serve")
+        .env("APP_ENVIRONMENT", "test")
+        .env("APP__DATABASE__CONNECTION__TYPE", "Local")
+        .env("APP__DATABASE__CONNECTION__HOST", "127.0.0.1")
+        .env("APP__DATABASE__CONNECTION__PORT", "1")
+        .env("APP__DATABASE__RETRY__MAX_ATTEMPTS", "1")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to connect to the database"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
@@ -0,0 +1,213 @@
+use reqwest::{Client, Response};
+use rush_data_server::model::audit::{AuditAction, AuditPage};
+use serde_json::{json, Value};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+async fn get_audit(address: &str, instance: Option<&str>, query: &[(&str, &str)]) -> Response {
+    let mut request = Client::new().get(format!("{address}/audit")).query(query);
+    if let Some(instance) = instance {
+        request = request.header("Host", instance_host(instance));
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn audit_page(address: &str, instance: Option<&str>, query: &[(&str, &str)]) -> AuditPage {
+    let response = get_audit(address, instance, query).await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[actix_web::test]
+async fn root_events_record_the_actor_and_request() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    let client = Client::new();
+    let response = client
+        .post(format!("{address}/instance"))
+        .header("X-Api-Key", "operator")
+        .header("X-Request-Id", "create-acme")
+        .json(&json!({ "name": "acme" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let response = client
+        .put(format!("{address}/instance/acme/limits"))
+        .json(&json!({ "max_records": 10 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let page = audit_page(&address, None, &[]).await;
+
+    let actions: Vec<_> = page.events.iter().map(|event| event.action).collect();
+    assert_eq!(
+        vec![
+            AuditAction::InstanceLimitsUpdated,
+            AuditAction::InstanceCreated
+        ],
+        actions
+    );
+    let created = &page.events[1];
+    assert_eq!("acme", created.target);
+    // API keys are not verified, so they say nothing about who made the request.
+    assert_eq!("anonymous", created.actor);
+    assert_eq!(Some("create-acme"), created.request_id.as_deref());
+    assert_eq!("anonymous", page.events[0].actor);
+    assert_eq!(json!(10), page.events[0].details["limits"]["max_records"]);
+}
+
+#[actix_web::test]
+async fn record_events_hold_the_changed_fields() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let client = Client::new();
+    let host = instance_host("acme");
+    let created: Value = client
+        .post(format!("{address}/api/contact"))
+        .header("Host", &host)
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["id"].as_str().unwrap();
+    client
+        .patch(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .json(&json!({ "name": "Ada", "email": "ada@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    client
+        .delete(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+
+    let target = format!("contact:{id}");
+    let page = audit_page(&address, Some("acme"), &[("target", &target)]).await;
+
+    let changes: Vec<_> = page
+        .events
+        .iter()
+        .map(|event| (event.action, event.details["changes"].clone()))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                AuditAction::RecordDeleted,
+                json!({
+                    "name": { "before": "Ada", "after": null },
+                    "email": { "before": "ada@example.com", "after": null },
+                })
+            ),
+            (
+                AuditAction::RecordUpdated,
+                json!({ "email": { "before": null, "after": "ada@example.com" } })
+            ),
+            (
+                AuditAction::RecordCreated,
+                json!({ "name": { "before": null, "after": "Ada" } })
+            ),
+        ],
+        changes
+    );
+
+    let page = audit_page(&address, Some("acme"), &[("action", "object_published")]).await;
+    assert_eq!(1, page.events.len());
+    assert_eq!("contact", page.events[0].target);
+
+    // Instance events stay out of the root log and vice versa.
+    let page = audit_page(&address, None, &[("action", "record_created")]).await;
+    assert!(page.events.is_empty());
+}
+
+#[actix_web::test]
+async fn events_are_paginated() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    for name in ["first", "second", "third"] {
+        create_instance(&address, name).await;
+    }
+
+    let page = audit_page(&address, None, &[("limit", "2")]).await;
+    assert_eq!(
+        vec!["third", "second"],
+        page.events
+            .iter()
+            .map(|event| event.target.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(2), page.next_offset);
+
+    let page = audit_page(&address, None, &[("limit", "2"), ("offset", "2")]).await;
+    assert_eq!(1, page.events.len());
+    assert_eq!("first", page.events[0].target);
+    assert_eq!(None, page.next_offset);
+
+    let response = get_audit(&address, None, &[("limit", "0")]).await;
+    assert_eq!(400, response.status().as_u16());
+    let response = get_audit(&address, None, &[("action", "unknown")]).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn the_audit_log_is_append_only() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    db.use_ns("root").use_db("root").await.unwrap();
+
+    for statement in [
+        "DELETE audit_event",
+        "UPDATE audit_event SET actor = 'someone else'",
+    ] {
+        let result = db.query(statement).await.unwrap().check();
+        assert!(result.is_err(), "`{statement}` succeeded");
+    }
+
+    let page = audit_page(&address, None, &[]).await;
+    assert_eq!(1, page.events.len());
+    assert_eq!("anonymous", page.events[0].actor);
+}
+
+#[actix_web::test]
+async fn changes_are_undone_when_their_event_cannot_be_appended() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    db.invalidate().await.unwrap();
+    db.use_ns("acme").use_db("acme").await.unwrap();
+    db.query("DEFINE FIELD target ON audit_event TYPE string ASSERT $value != 'blocked'")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let client = Client::new();
+    let host = instance_host("acme");
+    let response = client
+        .post(format!("{address}/schema/objects"))
+        .header("Host", &host)
+        .json(&json!({ "name": "blocked" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(500, response.status().as_u16());
+
+    let objects: Vec<Value> = client
+        .get(format!("{address}/schema/objects"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(objects.is_empty(), "{objects:?}");
+}
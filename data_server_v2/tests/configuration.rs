@@ -0,0 +1,267 @@
+use std::{env, fs, path::PathBuf, process::Command, sync::Mutex};
+
+use rush_data_server::configuration::get_configuration;
+use secrecy::ExposeSecret;
+
+// The configuration is read from the process environment, which is shared by
+// every test in this file.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn temp_dir() -> PathBuf {
+    let dir = env::temp_dir().join(format!("rush-config-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    dir
+}
+
+#[test]
+fn environment_variables_override_files() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("APP_ENVIRONMENT", "test");
+    env::set_var("APP__DATABASE__USERNAME", "from-env");
+    env::set_var("APP__APPLICATION__PORT", "9999");
+
+    let settings = get_configuration();
+
+    env::remove_var("APP__DATABASE__USERNAME");
+    env::remove_var("APP__APPLICATION__PORT");
+    let settings = settings.expect("Failed to read configuration.");
+    assert_eq!("from-env", settings.database.username);
+    assert_eq!(9999, settings.application.port);
+}
+
+#[test]
+fn secrets_can_be_read_from_files() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let secret = temp_dir().join("password");
+    fs::write(&secret, "from-file\n").unwrap();
+    env::set_var("APP_ENVIRONMENT", "test");
+    env::set_var("APP__DATABASE__PASSWORD_FILE", &secret);
+
+    let settings = get_configuration();
+
+    env::remove_var("APP__DATABASE__PASSWORD_FILE");
+    let settings = settings.expect("Failed to read configuration.");
+    assert_eq!("from-file", settings.database.password.expose_secret());
+}
+
+#[test]
+fn invalid_keys_are_reported_with_their_path() {
+    expect_invalid(
+        &[("APP__APPLICATION__PORT", "not-a-port")],
+        "`application.port` is invalid",
+    );
+}
+
+#[test]
+fn all_unknown_keys_are_reported() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("APP_ENVIRONMENT", "test");
+    env::set_var("APP__APPLICATION__COLOUR", "blue");
+    env::set_var("APP__DATABASE__PASWORD", "typo");
+
+    let error = get_configuration();
+
+    env::remove_var("APP__APPLICATION__COLOUR");
+    env::remove_var("APP__DATABASE__PASWORD");
+    let error = error
+        .expect_err("Unknown keys should be rejected")
+        .to_string();
+    for key in [
+        "`application.colour` is not a known setting",
+        "`database.pasword` is not a known setting",
+    ] {
+        assert!(error.contains(key), "{key} not reported in: {error}");
+    }
+}
+
+#[test]
+fn missing_keys_are_reported_with_their_path() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = temp_dir();
+    let config = fs::read_to_string("config/config.yaml").unwrap();
+    let config = config.replace("  delivery_concurrency: 16\n", "");
+    fs::write(dir.join("config.yaml"), config).unwrap();
+    fs::copy("config/config.test.yaml", dir.join("config.test.yaml")).unwrap();
+    env::set_var("APP_ENVIRONMENT", "test");
+    env::set_var("APP_CONFIG_DIR", &dir);
+
+    let error = get_configuration();
+
+    env::remove_var("APP_CONFIG_DIR");
+    let error = error
+        .expect_err("An incomplete configuration should fail")
+        .to_string();
+    assert!(
+        error.contains("`webhooks.delivery_concurrency` is missing"),
+        "`webhooks.delivery_concurrency` not reported in: {error}"
+    );
+}
+
+fn expect_invalid(variables: &[(&str, &str)], reported: &str) {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("APP_ENVIRONMENT", "test");
+    for (key, value) in variables {
+        env::set_var(key, value);
+    }
+
+    let result = get_configuration();
+
+    for (key, _) in variables {
+        env::remove_var(key);
+    }
+    env::set_var("APP_ENVIRONMENT", "test");
+    let error = result.expect_err("The configuration should be rejected");
+    assert!(
+        error.to_string().contains(reported),
+        "{reported} not reported in: {error}"
+    );
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    expect_invalid(&[("APP__DATABASE__PASWORD", "typo")], "pasword");
+}
+
+#[test]
+fn ranges_are_validated() {
+    expect_invalid(
+        &[("APP__DATABASE__POOL__MAX_CONNECTIONS", "0")],
+        "`database.pool.max_connections` must be at least 1",
+    );
+}
+
+#[test]
+fn rate_limits_must_admit_requests() {
+    expect_invalid(
+        &[
+            ("APP__LIMITS__INSTANCE__REQUESTS_PER_SECOND", "0"),
+            ("APP__LIMITS__API_KEY__BURST", "0"),
+        ],
+        "`limits.instance.requests_per_second` must be positive",
+    );
+}
+
+#[test]
+fn usage_must_be_aggregated_periodically() {
+    expect_invalid(
+        &[("APP__USAGE__AGGREGATION_INTERVAL_SECS", "0")],
+        "`usage.aggregation_interval_secs` must be at least 1",
+    );
+}
+
+#[test]
+fn recycle_bins_must_be_purged_periodically() {
+    expect_invalid(
+        &[("APP__RECYCLE_BIN__PURGE_INTERVAL_SECS", "0")],
+        "`recycle_bin.purge_interval_secs` must be at least 1",
+    );
+}
+
+#[test]
+fn webhook_hosts_are_allowed_as_written_in_urls() {
+    expect_invalid(
+        &[(
+            "APP__WEBHOOKS__ALLOWED_HOSTS",
+            "localhost,Receiver.Internal",
+        )],
+        "`webhooks.allowed_hosts` must hold hosts as written in URLs, not `Receiver.Internal`",
+    );
+}
+
+#[test]
+fn webhook_backoff_must_not_start_above_its_maximum() {
+    expect_invalid(
+        &[("APP__WEBHOOKS__RETRY__INITIAL_BACKOFF_MS", "5000000")],
+        "`webhooks.retry.initial_backoff_ms` must not exceed `max_backoff_ms`",
+    );
+}
+
+#[test]
+fn remote_connections_require_tls() {
+    expect_invalid(
+        &[
+            ("APP__DATABASE__CONNECTION__TYPE", "Remote"),
+            ("APP__DATABASE__CONNECTION__HOST", "db.example.com"),
+            ("APP__DATABASE__CONNECTION__PORT", "443"),
+        ],
+        "`database.connection.tls` must be enabled for Remote",
+    );
+}
+
+#[test]
+fn prod_forbids_in_memory_databases() {
+    expect_invalid(
+        &[
+            ("APP_ENVIRONMENT", "prod"),
+            ("APP__DATABASE__CONNECTION__TYPE", "InMemory"),
+        ],
+        "cannot be InMemory in prod",
+    );
+}
+
+#[test]
+fn credentials_key_must_be_set() {
+    expect_invalid(
+        &[("APP__DATABASE__CREDENTIALS_KEY", "")],
+        "`database.credentials_key` must be set",
+    );
+}
+
+#[test]
+fn tls_certificate_files_must_exist() {
+    expect_invalid(
+        &[
+            ("APP__APPLICATION__TLS__CERT_PATH", "/nonexistent/cert.pem"),
+            ("APP__APPLICATION__TLS__KEY_PATH", "/nonexistent/key.pem"),
+            ("APP__APPLICATION__TLS__RELOAD_INTERVAL_SECS", "30"),
+        ],
+        "`application.tls.cert_path` `/nonexistent/cert.pem` does not exist",
+    );
+}
+
+#[test]
+fn check_config_prints_the_redacted_configuration() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .arg("--check-config")
+        .env("APP_ENVIRONMENT", "test")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(output.status.success());
+    let settings: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Configuration is not json");
+    assert_eq!("[REDACTED]", settings["database"]["password"]);
+    assert_eq!("[REDACTED]", settings["database"]["credentials_key"]);
+    assert_eq!("InMemory", settings["database"]["connection"]["type"]);
+}
+
+#[test]
+fn config_check_subcommand_checks_the_configuration() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .args(["config", "check"])
+        .env("APP_ENVIRONMENT", "test")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(output.status.success());
+    let settings: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Configuration is not json");
+    assert_eq!("[REDACTED]", settings["database"]["password"]);
+}
+
+#[test]
+fn check_config_fails_on_invalid_configuration() {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let output = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .arg("--check-config")
+        .env("APP_ENVIRONMENT", "test")
+        .env("APP__DATABASE__RETRY__MAX_ATTEMPTS", "0")
+        .output()
+        .expect("Failed to run the server binary");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`database.retry.max_attempts` must be at least 1"));
+}
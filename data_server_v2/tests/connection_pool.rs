@@ -0,0 +1,111 @@
+use rush_data_server::{
+    audit::Actor,
+    configuration::get_configuration,
+    database::{
+        instances::delete_instance,
+        pool::{Binding, ConnectionPool},
+    },
+    model::audit::AuditAction,
+};
+use serde_json::Map;
+
+use crate::util::{create_instance, spawn_app};
+
+mod util;
+
+#[actix_web::test]
+async fn pool_hands_out_connections_bound_to_the_instance() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let pool = ConnectionPool::new(&settings.database, db).expect("Failed to create pool");
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "my-instance" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let instance = pool
+        .get(Binding::Instance("my-instance".into()))
+        .await
+        .expect("Failed to acquire instance connection");
+    let ns: Option<String> = instance
+        .query("RETURN session::ns()")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(Some("my-instance".to_string()), ns);
+    drop(instance);
+
+    let root = pool
+        .get(Binding::Root)
+        .await
+        .expect("Failed to acquire root connection");
+    let ns: Option<String> = root
+        .query("RETURN session::ns()")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(Some("root".to_string()), ns);
+}
+
+#[actix_web::test]
+async fn pool_times_out_when_no_connection_is_available() {
+    let (_, db) = spawn_app().await.expect("Failed to spawn app.");
+    let mut settings = get_configuration().expect("Failed to read configuration.");
+    settings.database.pool.acquire_timeout_secs = 1;
+    let pool = ConnectionPool::new(&settings.database, db).expect("Failed to create pool");
+
+    let held = pool.get(Binding::Root).await.expect("Failed to acquire");
+    assert_eq!(1, pool.status().active);
+
+    assert!(pool.get(Binding::Root).await.is_err());
+
+    drop(held);
+    assert_eq!(0, pool.status().active);
+    assert!(pool.get(Binding::Root).await.is_ok());
+}
+
+#[actix_web::test]
+async fn pool_rejects_unknown_instances() {
+    let (_, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let pool = ConnectionPool::new(&settings.database, db).expect("Failed to create pool");
+
+    assert!(pool.get(Binding::Instance("missing".into())).await.is_err());
+}
+
+#[actix_web::test]
+async fn pool_reloads_the_credentials_of_recreated_instances() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let pool = ConnectionPool::new(&settings.database, db).expect("Failed to create pool");
+    create_instance(&address, "acme").await;
+    drop(pool.get(Binding::Instance("acme".into())).await.unwrap());
+
+    // Recreated behind the back of this pool, which still caches the old credentials.
+    let root = pool.get(Binding::Root).await.unwrap();
+    let actor = Actor::cli();
+    let event = actor.event(AuditAction::InstanceDeleted, Map::new());
+    delete_instance(&root, "acme", &event).await.unwrap();
+    drop(root);
+    create_instance(&address, "acme").await;
+
+    let instance = pool
+        .get(Binding::Instance("acme".into()))
+        .await
+        .expect("Failed to acquire instance connection");
+    let ns: Option<String> = instance
+        .query("RETURN session::ns()")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(Some("acme".to_string()), ns);
+}
@@ -1,4 +1,14 @@
-use rush_data_server::model::instance::Instance;
+// The tests from before credentials were provisioned are kept as written.
+#![allow(clippy::get_first, clippy::needless_borrows_for_generic_args)]
+
+use rush_data_server::{
+    configuration::get_configuration,
+    database::credentials::{get_instance_credentials, SecretCipher},
+    error::ErrorBody,
+    model::instance::Instance,
+};
+use secrecy::ExposeSecret;
+use surrealdb::opt::auth::Database;
 
 use crate::util::spawn_app;
 
@@ -53,5 +63,167 @@ async fn subscribe_returns_a_400_when_data_is_missing() {
             "The API did not fail with 400 Bad Request when the payload was {}.",
             error_message
         );
+
+        let body: ErrorBody = response.json().await.expect("Error body is not json");
+        assert_eq!("bad_request", body.code);
+        assert!(body.details.is_some());
+        assert!(body.request_id.is_some());
+    }
+}
+
+#[actix_web::test]
+async fn create_instance_returns_a_400_for_invalid_names() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "Not Valid" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+    let body: ErrorBody = response.json().await.expect("Error body is not json");
+    assert_eq!("bad_request", body.code);
+}
+
+#[actix_web::test]
+async fn create_instance_returns_a_409_for_duplicate_names() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    let client = reqwest::Client::new();
+
+    for expected in [200, 409] {
+        let response = client
+            .post(format!("{address}/instance"))
+            .header("Content-Type", "application/json")
+            .body(r#"{ "name": "taken" }"#)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(expected, response.status().as_u16());
+
+        if expected == 409 {
+            let body: ErrorBody = response.json().await.expect("Error body is not json");
+            assert_eq!("conflict", body.code);
+            let details = body.details.expect("Conflict without details");
+            assert_eq!("name", details["field"]);
+            assert_eq!("taken", details["value"]);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn instance_availability_reports_taken_and_invalid_names() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "taken" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    for (name, available) in [("free", true), ("taken", false), ("Not Valid", false)] {
+        let response = client
+            .get(format!("{address}/instance/availability"))
+            .query(&[("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, response.status().as_u16());
+
+        let body: serde_json::Value = response.json().await.expect("Invalid json");
+        assert_eq!(
+            available, body["available"],
+            "Unexpected availability of {name}"
+        );
+        assert_eq!(available, body["reason"].is_null());
     }
 }
+
+#[actix_web::test]
+async fn instance_availability_requires_a_name() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::get(format!("{address}/instance/availability"))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn create_instance_provisions_database_credentials() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let cipher = SecretCipher::try_from(&settings.database.credentials_key).unwrap();
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "my-instance" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+
+    db.use_ns("root").use_db("root").await.unwrap();
+    let credentials = get_instance_credentials(&db, &cipher, "my-instance")
+        .await
+        .expect("Failed to load the instance credentials");
+
+    db.signin(Database {
+        namespace: "my-instance",
+        database: "my-instance",
+        username: &credentials.username,
+        password: credentials.password.expose_secret(),
+    })
+    .await
+    .expect("Failed to sign in with the instance credentials");
+}
+
+#[actix_web::test]
+async fn failed_provisioning_leaves_the_name_free() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let client = reqwest::Client::new();
+    let create = || {
+        client
+            .post(format!("{address}/instance"))
+            .json(&serde_json::json!({ "name": "acme" }))
+            .send()
+    };
+
+    // Storing the credentials fails once the namespace, database and user are defined.
+    db.use_ns("root").use_db("root").await.unwrap();
+    db.query("CREATE instance_credential SET instance_name = 'acme', username = 'x', secret = 'x'")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    let response = create().await.expect("Failed to execute request.");
+    assert!(!response.status().is_success());
+
+    db.use_ns("root").use_db("root").await.unwrap();
+    let namespaces: Option<serde_json::Value> = db
+        .query("INFO FOR ROOT")
+        .await
+        .unwrap()
+        .take("namespaces")
+        .unwrap();
+    assert!(namespaces.unwrap().get("acme").is_none());
+
+    db.query("DELETE instance_credential WHERE instance_name = 'acme'")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    let response = create().await.expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
@@ -19,3 +19,51 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[actix_web::test]
+async fn liveness_check_works() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::get(format!("{address}/health/live"))
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(response.status().is_success());
+}
+
+#[actix_web::test]
+async fn readiness_check_reports_components() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::get(format!("{address}/health/ready"))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.expect("Invalid json");
+    assert_eq!("up", body["status"]);
+    assert_eq!("up", body["components"]["database"]["status"]);
+    assert_eq!("up", body["components"]["migrations"]["status"]);
+    assert!(body["components"]["database"]["latency_ms"].is_number());
+}
+
+#[actix_web::test]
+async fn readiness_check_fails_when_migrations_are_not_applied() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    db.query("USE NS root DB root; DELETE migration WHERE version = 1")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let response = reqwest::get(format!("{address}/health/ready"))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(503, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.expect("Invalid json");
+    assert_eq!("down", body["status"]);
+    assert_eq!("up", body["components"]["database"]["status"]);
+    assert_eq!("down", body["components"]["migrations"]["status"]);
+    assert!(body["components"]["migrations"]["error"].is_string());
+}
@@ -0,0 +1,32 @@
+use std::net::TcpListener;
+
+use rush_data_server::{
+    configuration::{get_configuration, ConnectionSettings, ConnectionType},
+    database::{init_db, DatabaseError},
+};
+
+#[actix_web::test]
+async fn init_db_reports_unreachable_databases() {
+    std::env::set_var("APP_ENVIRONMENT", "test");
+    let mut settings = get_configuration().expect("Failed to read configuration.");
+    settings.database.retry.max_attempts = 2;
+    settings.database.retry.initial_backoff_ms = 10;
+
+    // Bind and release a port so that nothing is listening on it.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    settings.database.connection = ConnectionType::Local(ConnectionSettings {
+        host: "127.0.0.1".into(),
+        port,
+        tls: false,
+    });
+
+    let error = init_db(&settings.database)
+        .await
+        .expect_err("Connecting to a closed port should fail");
+
+    assert!(matches!(error, DatabaseError::Connection { .. }));
+}
@@ -0,0 +1,83 @@
+use rush_data_server::{
+    configuration::get_configuration,
+    database::{
+        instance_migrations::{migrate_instances, InstanceMigrationStatus, MigrationState},
+        migrations::{applied_migrations, instance_migrations},
+        pool::{Binding, ConnectionPool},
+    },
+    shutdown::Shutdown,
+};
+use std::time::Duration;
+
+use crate::util::spawn_app;
+
+mod util;
+
+async fn get_statuses(address: &str) -> Vec<InstanceMigrationStatus> {
+    reqwest::Client::new()
+        .get(format!("{address}/instance/migrations"))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse migration statuses")
+}
+
+#[actix_web::test]
+async fn new_instances_are_migrated_to_the_latest_version() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let pool = ConnectionPool::new(&settings.database, db).expect("Failed to create pool");
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "my-instance" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let migrations = instance_migrations().unwrap();
+    let instance = pool
+        .get(Binding::InstanceSchema("my-instance".into()))
+        .await
+        .unwrap();
+    let applied = applied_migrations(&instance).await.unwrap();
+    assert_eq!(migrations.len(), applied.len());
+    drop(instance);
+
+    let statuses = get_statuses(&address).await;
+    assert_eq!(1, statuses.len());
+    assert_eq!("my-instance", statuses[0].instance_name);
+    assert_eq!(MigrationState::UpToDate, statuses[0].state);
+    assert_eq!(migrations.last().unwrap().version, statuses[0].version);
+}
+
+#[actix_web::test]
+async fn failed_instance_migrations_are_reported() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    let settings = get_configuration().expect("Failed to read configuration.");
+    let pool = ConnectionPool::new(&settings.database, db.clone()).expect("Failed to create pool");
+
+    // An instance without provisioned credentials cannot be connected to.
+    db.use_ns("root").use_db("root").await.unwrap();
+    db.query("CREATE instance SET name = 'orphan'")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let statuses = get_statuses(&address).await;
+    assert_eq!(MigrationState::Behind, statuses[0].state);
+    assert_eq!(0, statuses[0].version);
+
+    migrate_instances(&pool, 2, &Shutdown::new(Duration::from_secs(1)))
+        .await
+        .expect("Failed to walk instances");
+
+    let statuses = get_statuses(&address).await;
+    assert_eq!(MigrationState::Failed, statuses[0].state);
+    assert!(statuses[0].error.is_some());
+}
@@ -0,0 +1,248 @@
+use reqwest::{header::RETRY_AFTER, Client, Method, Response};
+use rush_data_server::{
+    audit::Actor,
+    database::instances::set_instance_state,
+    error::ErrorBody,
+    model::{audit::AuditAction, instance::InstanceState},
+};
+use serde_json::{json, Map, Value};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+async fn set_limits(address: &str, instance: &str, limits: Value) -> Response {
+    Client::new()
+        .put(format!("{address}/instance/{instance}/limits"))
+        .json(&limits)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_instance(address: &str, instance: &str, api_key: Option<&str>) -> Response {
+    let mut request = Client::new()
+        .get(format!("{address}/"))
+        .header("Host", instance_host(instance));
+    if let Some(api_key) = api_key {
+        request = request.header("X-Api-Key", api_key);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn create_contact(address: &str, instance: &str, contact: Value) -> Response {
+    Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host(instance))
+        .json(&contact)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_web::test]
+async fn requests_over_the_instance_limit_return_429_with_retry_after() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "noisy").await;
+    create_instance(&address, "quiet").await;
+    let response = set_limits(
+        &address,
+        "noisy",
+        json!({ "instance": { "requests_per_second": 0.1, "burst": 2 } }),
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+
+    for _ in 0..2 {
+        assert_eq!(200, get_instance(&address, "noisy", None).await.status());
+    }
+    let response = get_instance(&address, "noisy", None).await;
+
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: u64 = response.headers()[RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=10).contains(&retry_after), "{retry_after}");
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("too_many_requests", body.code);
+    assert!(body.request_id.is_some());
+
+    // Other instances keep their own budget.
+    assert_eq!(200, get_instance(&address, "quiet", None).await.status());
+}
+
+#[actix_web::test]
+async fn api_keys_are_limited_separately() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    set_limits(
+        &address,
+        "acme",
+        json!({ "api_key": { "requests_per_second": 0.1, "burst": 1 } }),
+    )
+    .await;
+
+    assert_eq!(
+        200,
+        get_instance(&address, "acme", Some("first")).await.status()
+    );
+    assert_eq!(
+        429,
+        get_instance(&address, "acme", Some("first")).await.status()
+    );
+    assert_eq!(
+        200,
+        get_instance(&address, "acme", Some("second"))
+            .await
+            .status()
+    );
+    assert_eq!(200, get_instance(&address, "acme", None).await.status());
+}
+
+#[actix_web::test]
+async fn root_requests_are_not_rate_limited() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    for _ in 0..150 {
+        let response = reqwest::get(format!("{address}/health_check"))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+}
+
+#[actix_web::test]
+async fn requests_to_a_suspended_instance_return_403() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "dormant").await;
+    db.use_ns("root").use_db("root").await.unwrap();
+    let actor = Actor::cli();
+    let event = actor.event(AuditAction::InstanceSuspended, Map::new());
+    set_instance_state(&db, "dormant", InstanceState::Suspended, &event)
+        .await
+        .expect("Failed to suspend instance");
+
+    let response = create_contact(&address, "dormant", json!({ "name": "Ada" })).await;
+
+    assert_eq!(403, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("instance_suspended", body.code);
+    assert_eq!(403, get_instance(&address, "dormant", None).await.status());
+}
+
+#[actix_web::test]
+async fn writes_beyond_the_record_quota_return_403() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    set_limits(&address, "acme", json!({ "max_records": 1 })).await;
+
+    let response = create_contact(&address, "acme", json!({ "name": "Ada" })).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = create_contact(&address, "acme", json!({ "name": "Grace" })).await;
+    assert_eq!(403, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("quota_exceeded", body.code);
+    assert_eq!(
+        Some(json!({ "quota": "records", "limit": 1, "used": 1 })),
+        body.details
+    );
+}
+
+#[actix_web::test]
+async fn writes_beyond_the_storage_quota_return_403() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    set_limits(&address, "acme", json!({ "max_storage_bytes": 200 })).await;
+
+    let response = create_contact(&address, "acme", json!({ "name": "Ada" })).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let response = create_contact(&address, "acme", json!({ "notes": "x".repeat(200) })).await;
+    assert_eq!(403, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("quota_exceeded", body.code);
+    assert_eq!(
+        Some(json!("storage_bytes")),
+        body.details.map(|d| d["quota"].clone())
+    );
+}
+
+async fn send_contact(address: &str, method: Method, path: &str, contact: Value) -> Response {
+    Client::new()
+        .request(method, format!("{address}{path}"))
+        .header("Host", instance_host("acme"))
+        .json(&contact)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_web::test]
+async fn updates_are_charged_with_how_much_the_record_grows() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    set_limits(&address, "acme", json!({ "max_storage_bytes": 200 })).await;
+    let response = create_contact(&address, "acme", json!({ "notes": "x".repeat(120) })).await;
+    assert_eq!(201, response.status().as_u16());
+    let contact: Value = response.json().await.unwrap();
+    let path = format!("/api/contact/{}", contact["id"].as_str().unwrap());
+
+    let same_size = json!({ "notes": "y".repeat(120) });
+    let response = send_contact(&address, Method::PATCH, &path, same_size).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let grown = json!({ "notes": "z".repeat(200) });
+    let response = send_contact(&address, Method::PATCH, &path, grown).await;
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn records_in_the_recycle_bin_do_not_count_towards_quotas() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    set_limits(&address, "acme", json!({ "max_records": 1 })).await;
+    let response = create_contact(&address, "acme", json!({ "name": "Ada" })).await;
+    let ada: Value = response.json().await.unwrap();
+    let ada = ada["id"].as_str().unwrap();
+
+    let path = format!("/api/contact/{ada}");
+    let response = send_contact(&address, Method::DELETE, &path, json!({})).await;
+    assert_eq!(204, response.status().as_u16());
+    let response = create_contact(&address, "acme", json!({ "name": "Grace" })).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let path = format!("/recycle-bin/records/contact/{ada}/restore");
+    let response = send_contact(&address, Method::POST, &path, json!({})).await;
+    assert_eq!(403, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!(
+        Some(json!({ "quota": "records", "limit": 1, "used": 1 })),
+        body.details
+    );
+}
+
+#[actix_web::test]
+async fn setting_limits_of_an_unknown_instance_returns_404() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = set_limits(&address, "missing", json!({ "max_records": 1 })).await;
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn unknown_limit_fields_are_rejected() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+
+    let response = set_limits(&address, "acme", json!({ "max_users": 1 })).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
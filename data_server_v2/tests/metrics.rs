@@ -0,0 +1,59 @@
+use crate::util::{spawn_app, spawn_app_with_admin};
+
+mod util;
+
+#[actix_web::test]
+async fn metrics_report_requests_pool_and_instances() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    let client = reqwest::Client::new();
+
+    client
+        .get(format!("{address}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let response = client
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .body(r#"{ "name": "metered" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client
+        .get(format!("{address}/metrics"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let body = response.text().await.expect("Failed to read body");
+    assert!(
+        body.contains(r#"http_requests_total{method="GET",route="/health_check",status="200"}"#)
+    );
+    assert!(body.contains("http_request_duration_seconds_bucket"));
+    assert!(body.contains("surrealdb_query_duration_seconds_count"));
+    assert!(body.contains(r#"surrealdb_pool_connections{state="active"}"#));
+    assert!(body.contains(r#"surrealdb_pool_connections{state="idle"}"#));
+    assert!(body.contains("surrealdb_pool_max_connections 1"));
+    assert!(body.contains(r#"instances{state="active"}"#));
+}
+
+#[actix_web::test]
+async fn metrics_can_be_served_on_the_admin_port() {
+    let (address, admin_address, _) = spawn_app_with_admin(true)
+        .await
+        .expect("Failed to spawn app.");
+    let admin_address = admin_address.expect("No admin address");
+
+    let response = reqwest::get(format!("{address}/metrics"))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, response.status().as_u16());
+
+    let response = reqwest::get(format!("{admin_address}/metrics"))
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
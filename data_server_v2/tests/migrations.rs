@@ -0,0 +1,52 @@
+use rush_data_server::database::migrations::{applied_migrations, migrate, root_migrations};
+
+use crate::util::spawn_app;
+
+mod util;
+
+#[actix_web::test]
+async fn root_migrations_are_applied_on_startup() {
+    let (_, db) = spawn_app().await.expect("Failed to spawn app.");
+    db.use_ns("root").use_db("root").await.unwrap();
+
+    let migrations = root_migrations().expect("Failed to load migrations");
+    let applied = applied_migrations(&db)
+        .await
+        .expect("Failed to list migrations");
+
+    assert_eq!(migrations.len(), applied.len());
+    for (migration, applied) in migrations.iter().zip(applied) {
+        assert_eq!(migration.version, applied.version);
+        assert_eq!(migration.checksum, applied.checksum);
+    }
+}
+
+#[actix_web::test]
+async fn migrate_is_a_no_op_when_up_to_date() {
+    let (_, db) = spawn_app().await.expect("Failed to spawn app.");
+    db.use_ns("root").use_db("root").await.unwrap();
+
+    let migrations = root_migrations().expect("Failed to load migrations");
+    let applied = migrate(&db, &migrations).await.expect("Failed to migrate");
+
+    assert!(applied.is_empty());
+}
+
+#[actix_web::test]
+async fn migrate_fails_on_checksum_mismatch() {
+    let (_, db) = spawn_app().await.expect("Failed to spawn app.");
+    db.use_ns("root").use_db("root").await.unwrap();
+
+    db.query("UPDATE migration SET checksum = 'tampered' WHERE version = 1")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let migrations = root_migrations().expect("Failed to load migrations");
+    let error = migrate(&db, &migrations)
+        .await
+        .expect_err("Migrating with a changed migration should fail");
+
+    assert!(error.to_string().contains("Checksum mismatch"));
+}
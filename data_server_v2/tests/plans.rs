@@ -0,0 +1,220 @@
+use reqwest::{Client, Response};
+use rush_data_server::{error::ErrorBody, model::plan::Plan};
+use serde_json::{json, Value};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+async fn put_plan(address: &str, name: &str, entitlements: Value) -> Response {
+    Client::new()
+        .put(format!("{address}/plan/{name}"))
+        .json(&entitlements)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn assign_plan(address: &str, instance: &str, plan: Option<&str>) -> Response {
+    Client::new()
+        .put(format!("{address}/instance/{instance}/plan"))
+        .json(&json!({ "plan": plan }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn create_contact(address: &str, instance: &str) -> Response {
+    Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host(instance))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn create_object(address: &str, instance: &str, name: &str) -> Response {
+    Client::new()
+        .post(format!("{address}/schema/objects"))
+        .header("Host", instance_host(instance))
+        .json(&json!({ "name": name }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[actix_web::test]
+async fn plans_can_be_created_replaced_and_listed() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = put_plan(&address, "pro", json!({ "max_records": 10 })).await;
+    assert_eq!(200, response.status().as_u16());
+    let response = put_plan(
+        &address,
+        "pro",
+        json!({ "max_records": 100, "features": ["api"] }),
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+    put_plan(&address, "free", json!({})).await;
+
+    let plans: Vec<Plan> = reqwest::get(format!("{address}/plan"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let names: Vec<_> = plans.iter().map(|plan| plan.name.as_str()).collect();
+    assert_eq!(vec!["free", "pro"], names);
+    assert_eq!(Some(100), plans[1].entitlements.max_records);
+}
+
+#[actix_web::test]
+async fn invalid_plans_are_rejected() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = put_plan(&address, "Pro Plan", json!({})).await;
+    assert_eq!(400, response.status().as_u16());
+    let response = put_plan(&address, "pro", json!({ "features": ["teleportation"] })).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn assigning_unknown_plans_or_instances_returns_404() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    put_plan(&address, "free", json!({})).await;
+
+    let response = assign_plan(&address, "acme", Some("missing")).await;
+    assert_eq!(404, response.status().as_u16());
+    let response = assign_plan(&address, "missing", Some("free")).await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn features_outside_the_plan_return_402() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    put_plan(&address, "free", json!({ "features": [] })).await;
+    assert_eq!(
+        200,
+        assign_plan(&address, "acme", Some("free")).await.status()
+    );
+
+    let response = create_contact(&address, "acme").await;
+
+    assert_eq!(402, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("payment_required", body.code);
+    assert_eq!(
+        "The `api` feature is not included in the `free` plan",
+        body.message
+    );
+
+    // Removing the plan lifts its restrictions.
+    assign_plan(&address, "acme", None).await;
+    assert_eq!(201, create_contact(&address, "acme").await.status());
+}
+
+#[actix_web::test]
+async fn plan_limits_apply_to_objects_and_records() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    put_plan(
+        &address,
+        "starter",
+        json!({ "max_objects": 1, "max_records": 1, "features": ["api"] }),
+    )
+    .await;
+    assign_plan(&address, "acme", Some("starter")).await;
+
+    publish_object(&address, "acme", "contact").await;
+    let response = create_object(&address, "acme", "company").await;
+    assert_eq!(403, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("quota_exceeded", body.code);
+
+    assert_eq!(201, create_contact(&address, "acme").await.status());
+    assert_eq!(403, create_contact(&address, "acme").await.status());
+}
+
+#[actix_web::test]
+async fn deleted_objects_do_not_count_towards_the_object_limit() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    put_plan(&address, "starter", json!({ "max_objects": 1 })).await;
+    assign_plan(&address, "acme", Some("starter")).await;
+    assert_eq!(
+        201,
+        create_object(&address, "acme", "contact").await.status()
+    );
+
+    let response = Client::new()
+        .delete(format!("{address}/schema/objects/contact"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(
+        201,
+        create_object(&address, "acme", "company").await.status()
+    );
+
+    // Restoring the deleted object would exceed the limit again.
+    let response = Client::new()
+        .post(format!("{address}/recycle-bin/objects/contact/restore"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn instance_overrides_take_precedence_over_the_plan() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    put_plan(
+        &address,
+        "starter",
+        json!({ "max_records": 1, "features": ["api"] }),
+    )
+    .await;
+    assign_plan(&address, "acme", Some("starter")).await;
+    Client::new()
+        .put(format!("{address}/instance/acme/limits"))
+        .json(&json!({ "max_records": 2 }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(201, create_contact(&address, "acme").await.status());
+    assert_eq!(201, create_contact(&address, "acme").await.status());
+    assert_eq!(403, create_contact(&address, "acme").await.status());
+}
+
+#[actix_web::test]
+async fn daily_api_calls_beyond_the_plan_return_429() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    put_plan(&address, "trial", json!({ "max_daily_api_calls": 2 })).await;
+    assign_plan(&address, "acme", Some("trial")).await;
+    let get = || {
+        Client::new()
+            .get(format!("{address}/"))
+            .header("Host", instance_host("acme"))
+            .send()
+    };
+
+    assert_eq!(200, get().await.unwrap().status());
+    assert_eq!(200, get().await.unwrap().status());
+    let response = get().await.unwrap();
+
+    assert_eq!(429, response.status().as_u16());
+    assert!(response.headers().contains_key("Retry-After"));
+}
@@ -0,0 +1,124 @@
+use rush_data_server::error::ErrorBody;
+use serde_json::{json, Value};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+#[actix_web::test]
+async fn records_can_be_created_read_updated_and_deleted() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let client = reqwest::Client::new();
+    let host = instance_host("acme");
+
+    let response = client
+        .post(format!("{address}/api/contact"))
+        .header("Host", &host)
+        .json(&json!({ "name": "Ada", "id": "ignored" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let created: Value = response.json().await.unwrap();
+    let id = created["id"].as_str().unwrap().to_owned();
+    assert_ne!("ignored", id);
+    assert_eq!("Ada", created["name"]);
+
+    let response = client
+        .patch(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .json(&json!({ "email": "ada@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let record: Value = client
+        .get(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        json!({ "id": id, "name": "Ada", "email": "ada@example.com" }),
+        record
+    );
+
+    let records: Vec<Value> = client
+        .get(format!("{address}/api/contact"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(vec![record], records);
+
+    let response = client
+        .delete(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+
+    let response = client
+        .get(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn updating_a_missing_record_returns_404_without_creating_it() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .patch(format!("{address}/api/contact/missing"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    let records: Vec<Value> = client
+        .get(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(records.is_empty());
+}
+
+#[actix_web::test]
+async fn unpublished_objects_are_not_found() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/api/object_table"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "sneaky" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("not_found", body.code);
+}
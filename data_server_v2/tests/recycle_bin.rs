@@ -0,0 +1,217 @@
+use reqwest::{Client, Response};
+use rush_data_server::model::recycle_bin::RecycleBin;
+use serde_json::{json, Value};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+struct Acme {
+    address: String,
+    client: Client,
+    /// The raw database, to change the instance behind the server's back.
+    db: Surreal<Any>,
+}
+
+impl Acme {
+    async fn new() -> Self {
+        let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+        create_instance(&address, "acme").await;
+        publish_object(&address, "acme", "contact").await;
+        Self {
+            address,
+            client: Client::new(),
+            db,
+        }
+    }
+
+    async fn send(&self, method: reqwest::Method, path: &str) -> Response {
+        self.client
+            .request(method, format!("{}{path}", self.address))
+            .header("Host", instance_host("acme"))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    async fn create_contact(&self, content: Value) -> String {
+        let record: Value = self
+            .client
+            .post(format!("{}/api/contact", self.address))
+            .header("Host", instance_host("acme"))
+            .json(&content)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        record["id"].as_str().unwrap().to_owned()
+    }
+
+    async fn recycle_bin(&self) -> RecycleBin {
+        let response = self.send(reqwest::Method::GET, "/recycle-bin").await;
+        assert_eq!(200, response.status().as_u16());
+        response.json().await.unwrap()
+    }
+}
+
+#[actix_web::test]
+async fn deleted_records_are_hidden_until_restored() {
+    let acme = Acme::new().await;
+    let id = acme
+        .create_contact(json!({ "name": "Ada", "deleted_at": "ignored" }))
+        .await;
+
+    let response = acme
+        .send(reqwest::Method::DELETE, &format!("/api/contact/{id}"))
+        .await;
+    assert_eq!(204, response.status().as_u16());
+    let response = acme
+        .send(reqwest::Method::GET, &format!("/api/contact/{id}"))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+    let records: Vec<Value> = acme
+        .send(reqwest::Method::GET, "/api/contact")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(records.is_empty());
+
+    let bin = acme.recycle_bin().await;
+    assert_eq!(1, bin.records.len());
+    assert_eq!("contact", bin.records[0].object);
+    assert_eq!(json!(id), bin.records[0].record["id"]);
+    assert!(bin.records[0].record["deleted_at"].is_string());
+
+    let response = acme
+        .send(
+            reqwest::Method::POST,
+            &format!("/recycle-bin/records/contact/{id}/restore"),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let record: Value = acme
+        .send(reqwest::Method::GET, &format!("/api/contact/{id}"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json!({ "id": id, "name": "Ada" }), record);
+    // The history of a restored record is kept.
+    let versions: Vec<Value> = acme
+        .send(reqwest::Method::GET, &format!("/api/contact/{id}/versions"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, versions.len());
+    assert!(acme.recycle_bin().await.records.is_empty());
+}
+
+#[actix_web::test]
+async fn purged_records_cannot_be_restored() {
+    let acme = Acme::new().await;
+    let id = acme.create_contact(json!({ "name": "Ada" })).await;
+
+    // Only deleted records can be purged.
+    let purge = format!("/recycle-bin/records/contact/{id}");
+    let response = acme.send(reqwest::Method::DELETE, &purge).await;
+    assert_eq!(404, response.status().as_u16());
+
+    acme.send(reqwest::Method::DELETE, &format!("/api/contact/{id}"))
+        .await;
+    let response = acme.send(reqwest::Method::DELETE, &purge).await;
+    assert_eq!(204, response.status().as_u16());
+
+    assert!(acme.recycle_bin().await.records.is_empty());
+    let response = acme
+        .send(reqwest::Method::POST, &format!("{purge}/restore"))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn records_stay_in_the_recycle_bin_unless_their_history_is_purged_too() {
+    let acme = Acme::new().await;
+    let id = acme.create_contact(json!({ "name": "Ada" })).await;
+    acme.send(reqwest::Method::DELETE, &format!("/api/contact/{id}"))
+        .await;
+    acme.db.invalidate().await.unwrap();
+    acme.db.use_ns("acme").use_db("acme").await.unwrap();
+    acme.db
+        .query("DEFINE EVENT kept ON TABLE record_version WHEN $event = 'DELETE' THEN { THROW 'kept' }")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let purge = format!("/recycle-bin/records/contact/{id}");
+    let response = acme.send(reqwest::Method::DELETE, &purge).await;
+    assert_eq!(500, response.status().as_u16());
+
+    let bin = acme.recycle_bin().await;
+    assert_eq!(1, bin.records.len());
+}
+
+#[actix_web::test]
+async fn deleted_objects_can_be_restored_or_purged() {
+    let acme = Acme::new().await;
+    let id = acme.create_contact(json!({ "name": "Ada" })).await;
+
+    let response = acme
+        .send(reqwest::Method::DELETE, "/schema/objects/contact")
+        .await;
+    assert_eq!(204, response.status().as_u16());
+    let response = acme
+        .send(reqwest::Method::GET, &format!("/api/contact/{id}"))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+    let objects: Vec<Value> = acme
+        .send(reqwest::Method::GET, "/schema/objects")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(objects.is_empty());
+    let bin = acme.recycle_bin().await;
+    assert_eq!(
+        vec!["contact"],
+        bin.objects
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect::<Vec<_>>()
+    );
+
+    let response = acme
+        .send(
+            reqwest::Method::POST,
+            "/recycle-bin/objects/contact/restore",
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let response = acme
+        .send(reqwest::Method::GET, &format!("/api/contact/{id}"))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+
+    acme.send(reqwest::Method::DELETE, "/schema/objects/contact")
+        .await;
+    let response = acme
+        .send(reqwest::Method::DELETE, "/recycle-bin/objects/contact")
+        .await;
+    assert_eq!(204, response.status().as_u16());
+    assert!(acme.recycle_bin().await.objects.is_empty());
+
+    // The name is free again, and the new object starts out empty.
+    publish_object(&acme.address, "acme", "contact").await;
+    let records: Vec<Value> = acme
+        .send(reqwest::Method::GET, "/api/contact")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(records.is_empty());
+}
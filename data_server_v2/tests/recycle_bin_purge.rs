@@ -0,0 +1,72 @@
+use reqwest::Client;
+use rush_data_server::model::recycle_bin::RecycleBin;
+use serde_json::{json, Value};
+use std::{env, time::Duration};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+#[actix_web::test]
+async fn expired_records_and_objects_are_purged_in_the_background() {
+    env::set_var("APP__RECYCLE_BIN__RETENTION_DAYS", "0");
+    env::set_var("APP__RECYCLE_BIN__PURGE_INTERVAL_SECS", "1");
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    publish_object(&address, "acme", "deal").await;
+    let client = Client::new();
+    let host = instance_host("acme");
+    let mut ids = Vec::new();
+    for name in ["Ada", "Grace"] {
+        let record: Value = client
+            .post(format!("{address}/api/contact"))
+            .header("Host", &host)
+            .json(&json!({ "name": name }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        ids.push(record["id"].as_str().unwrap().to_owned());
+    }
+    client
+        .delete(format!("{address}/api/contact/{}", ids[0]))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    client
+        .delete(format!("{address}/schema/objects/deal"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    let bin: RecycleBin = client
+        .get(format!("{address}/recycle-bin"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(bin.objects.is_empty());
+    assert!(bin.records.is_empty());
+    // Records that were not deleted are left alone.
+    let records: Vec<Value> = client
+        .get(format!("{address}/api/contact"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, records.len());
+    assert_eq!(json!(ids[1]), records[0]["id"]);
+}
@@ -0,0 +1,84 @@
+use rush_data_server::error::ErrorBody;
+
+use crate::util::spawn_app;
+
+mod util;
+
+#[actix_web::test]
+async fn request_id_is_generated_when_missing() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::get(format!("{address}/health_check"))
+        .await
+        .expect("Failed to execute request.");
+
+    let id = response
+        .headers()
+        .get("x-request-id")
+        .expect("No request id in the response");
+    assert!(!id.is_empty());
+}
+
+#[actix_web::test]
+async fn request_id_is_echoed_in_responses_and_error_bodies() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .header("X-Request-Id", "support-ticket-42")
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        Some("support-ticket-42"),
+        response
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+    );
+    let body: ErrorBody = response.json().await.expect("Error body is not json");
+    assert_eq!(Some("support-ticket-42".to_string()), body.request_id);
+}
+
+#[actix_web::test]
+async fn unsafe_request_ids_are_replaced() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::Client::new()
+        .get(format!("{address}/health_check"))
+        .header("X-Request-Id", "not allowed; drop table")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let id = response.headers().get("x-request-id").unwrap();
+    assert_ne!("not allowed; drop table", id);
+}
+
+#[actix_web::test]
+async fn request_id_is_passed_to_the_database_session() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/instance"))
+        .header("Content-Type", "application/json")
+        .header("X-Request-Id", "correlated")
+        .body(r#"{ "name": "correlated" }"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    // The in-memory pool shares its single connection with the returned handle.
+    let request_id: Option<String> = db
+        .query("RETURN $request_id")
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    assert_eq!(Some("correlated".to_string()), request_id);
+}
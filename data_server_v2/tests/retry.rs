@@ -0,0 +1,68 @@
+use std::cell::Cell;
+
+use rush_data_server::{configuration::RetrySettings, database::retry::with_retry};
+
+fn settings() -> RetrySettings {
+    RetrySettings {
+        max_attempts: 3,
+        initial_backoff_ms: 1,
+        max_backoff_ms: 5,
+    }
+}
+
+#[actix_web::test]
+async fn retries_transient_errors_until_success() {
+    let attempts = Cell::new(0);
+
+    let result = with_retry(
+        &settings(),
+        |_: &&str| true,
+        || async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err("unavailable")
+            } else {
+                Ok(attempts.get())
+            }
+        },
+    )
+    .await;
+
+    assert_eq!(Ok(3), result);
+}
+
+#[actix_web::test]
+async fn gives_up_after_the_configured_attempts() {
+    let attempts = Cell::new(0);
+
+    let result: Result<(), _> = with_retry(
+        &settings(),
+        |_: &&str| true,
+        || async {
+            attempts.set(attempts.get() + 1);
+            Err("unavailable")
+        },
+    )
+    .await;
+
+    assert_eq!(Err("unavailable"), result);
+    assert_eq!(3, attempts.get());
+}
+
+#[actix_web::test]
+async fn does_not_retry_permanent_errors() {
+    let attempts = Cell::new(0);
+
+    let result: Result<(), _> = with_retry(
+        &settings(),
+        |e: &&str| *e != "forbidden",
+        || async {
+            attempts.set(attempts.get() + 1);
+            Err("forbidden")
+        },
+    )
+    .await;
+
+    assert_eq!(Err("forbidden"), result);
+    assert_eq!(1, attempts.get());
+}
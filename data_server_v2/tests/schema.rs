@@ -0,0 +1,114 @@
+use reqwest::Client;
+use rush_data_server::{error::ErrorBody, model::object::ObjectTable};
+use serde_json::json;
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+#[actix_web::test]
+async fn objects_are_created_unpublished_and_can_be_published() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    let client = Client::new();
+
+    let response = client
+        .post(format!("{address}/schema/objects"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "contact", "settings": { "label": "Contact" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let object: ObjectTable = response.json().await.unwrap();
+    assert!(!object.published);
+    assert_eq!(Some(&json!("Contact")), object.settings.get("label"));
+
+    let response = client
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+
+    let response = client
+        .post(format!("{address}/schema/objects/contact/publish"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+
+    let objects: Vec<ObjectTable> = client
+        .get(format!("{address}/schema/objects"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, objects.len());
+    assert!(objects[0].published);
+}
+
+#[actix_web::test]
+async fn invalid_and_reserved_object_names_are_rejected() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+
+    for name in [
+        "",
+        "Contact",
+        "1contact",
+        "con-tact",
+        "object_table",
+        "migration",
+    ] {
+        let response = Client::new()
+            .post(format!("{address}/schema/objects"))
+            .header("Host", instance_host("acme"))
+            .json(&json!({ "name": name }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(400, response.status().as_u16(), "`{name}` was accepted");
+    }
+}
+
+#[actix_web::test]
+async fn duplicate_objects_return_409() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+
+    let response = Client::new()
+        .post(format!("{address}/schema/objects"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "contact" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(409, response.status().as_u16());
+    let body: ErrorBody = response.json().await.unwrap();
+    assert_eq!("An object named `contact` already exists", body.message);
+}
+
+#[actix_web::test]
+async fn publishing_an_unknown_object_returns_404() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+
+    let response = Client::new()
+        .post(format!("{address}/schema/objects/missing/publish"))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,114 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use crate::util::spawn_app_with_shutdown;
+
+mod util;
+
+#[actix_web::test]
+async fn triggering_shutdown_stops_the_server() {
+    let (address, shutdown, server) = spawn_app_with_shutdown()
+        .await
+        .expect("Failed to spawn app.");
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    shutdown.trigger();
+    actix_web::rt::time::timeout(Duration::from_secs(10), server)
+        .await
+        .expect("The server did not shut down in time")
+        .unwrap()
+        .expect("The server failed");
+
+    let result = client.get(format!("{address}/health_check")).send().await;
+    assert!(result.is_err());
+}
+
+#[actix_web::test]
+async fn in_flight_requests_are_drained() {
+    let (address, shutdown, server) = spawn_app_with_shutdown()
+        .await
+        .expect("Failed to spawn app.");
+    let host = address.trim_start_matches("http://").to_string();
+
+    // Only the headers and part of the body are sent before shutting down.
+    let body = r#"{ "name": "drained" }"#;
+    let (head, tail) = body.split_at(10);
+    let request = format!(
+        "POST /instance HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{head}",
+        body.len()
+    );
+    let mut stream = actix_web::rt::task::spawn_blocking(move || {
+        let mut stream = TcpStream::connect(host).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        stream
+    })
+    .await
+    .unwrap();
+    actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+
+    shutdown.trigger();
+    actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+
+    let tail = tail.to_string();
+    let response = actix_web::rt::task::spawn_blocking(move || {
+        stream.write_all(tail.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    })
+    .await
+    .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+
+    actix_web::rt::time::timeout(Duration::from_secs(10), server)
+        .await
+        .expect("The server did not shut down in time")
+        .unwrap()
+        .expect("The server failed");
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_shuts_the_server_down() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rush_data_server_bin"))
+        .env("APP_ENVIRONMENT", "test")
+        .env("APP__APPLICATION__PORT", "0")
+        .env("APP__TELEMETRY__FORMAT", "Compact")
+        .env("RUST_LOG", "info")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the server binary");
+
+    let mut output = BufReader::new(child.stdout.take().unwrap()).lines();
+    output
+        .by_ref()
+        .map_while(Result::ok)
+        .find(|line| line.contains("starting service"))
+        .expect("The server did not start");
+
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .expect("Failed to send SIGTERM");
+    assert!(status.success());
+
+    let output: Vec<String> = output.map_while(Result::ok).collect();
+    let status = child.wait().expect("Failed to wait for the server");
+    assert!(status.success(), "{output:?}");
+    assert!(output.iter().any(|line| line.contains("Received SIGTERM")));
+    assert!(output
+        .iter()
+        .any(|line| line.contains("Flushing telemetry")));
+}
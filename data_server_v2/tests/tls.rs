@@ -0,0 +1,144 @@
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rush_data_server::{
+    configuration::{DomainCertificate, TlsSettings, TlsVersion},
+    shutdown::Shutdown,
+    tls::server_config,
+};
+
+use crate::util::spawn_app_with_tls;
+
+mod util;
+
+struct TestCertificate {
+    cert_pem: String,
+    key_pem: String,
+}
+
+impl TestCertificate {
+    fn generate(domain: &str) -> Self {
+        let certificate = rcgen::generate_simple_self_signed(vec![domain.into()])
+            .expect("Failed to generate certificate");
+        Self {
+            cert_pem: certificate.serialize_pem().unwrap(),
+            key_pem: certificate.serialize_private_key_pem(),
+        }
+    }
+
+    fn write(&self, cert_path: &Path, key_path: &Path) {
+        fs::write(cert_path, &self.cert_pem).unwrap();
+        fs::write(key_path, &self.key_pem).unwrap();
+    }
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = env::temp_dir().join(format!("rush-tls-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    dir
+}
+
+/// A client that only trusts `certificate`, connecting to `domain` on the local port.
+fn client(certificate: &TestCertificate, domain: &str, port: u16) -> reqwest::Client {
+    let root = reqwest::Certificate::from_pem(certificate.cert_pem.as_bytes()).unwrap();
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(root)
+        .resolve(domain, SocketAddr::from(([127, 0, 0, 1], port)))
+        .build()
+        .unwrap()
+}
+
+/// Writes a default certificate for `rush.test` and one for `custom.example`.
+fn settings(dir: &Path, custom: &TestCertificate) -> (TlsSettings, TestCertificate) {
+    let default = TestCertificate::generate("rush.test");
+    default.write(&dir.join("default.pem"), &dir.join("default.key"));
+    custom.write(&dir.join("custom.pem"), &dir.join("custom.key"));
+
+    let settings = TlsSettings {
+        cert_path: dir.join("default.pem"),
+        key_path: dir.join("default.key"),
+        min_version: TlsVersion::Tls12,
+        certificates: vec![DomainCertificate {
+            domain: "custom.example".into(),
+            cert_path: dir.join("custom.pem"),
+            key_path: dir.join("custom.key"),
+        }],
+        reload_interval_secs: 1,
+    };
+    (settings, default)
+}
+
+#[actix_web::test]
+async fn certificates_are_selected_by_server_name() {
+    let dir = temp_dir();
+    let custom = TestCertificate::generate("custom.example");
+    let (settings, default) = settings(&dir, &custom);
+    let tls = server_config(&settings, &Shutdown::new(Duration::from_secs(1)))
+        .expect("Failed to load certificates");
+    let (port, _) = spawn_app_with_tls(tls).await.expect("Failed to spawn app.");
+
+    let response = client(&custom, "custom.example", port)
+        .get(format!("https://custom.example:{port}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client(&default, "rush.test", port)
+        .get(format!("https://rush.test:{port}/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+
+    // The default certificate is not presented for the custom domain.
+    let result = client(&default, "custom.example", port)
+        .get(format!("https://custom.example:{port}/health_check"))
+        .send()
+        .await;
+    assert!(result.is_err());
+}
+
+#[actix_web::test]
+async fn certificates_are_reloaded_when_files_change() {
+    let dir = temp_dir();
+    let (settings, _) = settings(&dir, &TestCertificate::generate("custom.example"));
+    let tls = server_config(&settings, &Shutdown::new(Duration::from_secs(1)))
+        .expect("Failed to load certificates");
+    let (port, _) = spawn_app_with_tls(tls).await.expect("Failed to spawn app.");
+
+    let renewed = TestCertificate::generate("custom.example");
+    renewed.write(&dir.join("custom.pem"), &dir.join("custom.key"));
+    let client = client(&renewed, "custom.example", port);
+
+    for _ in 0..50 {
+        let result = client
+            .get(format!("https://custom.example:{port}/health_check"))
+            .send()
+            .await;
+        if let Ok(response) = result {
+            assert_eq!(200, response.status().as_u16());
+            return;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The renewed certificate was never presented");
+}
+
+#[test]
+fn invalid_certificates_are_rejected() {
+    let dir = temp_dir();
+    let (mut settings, _) = settings(&dir, &TestCertificate::generate("custom.example"));
+    fs::write(dir.join("empty.pem"), "").unwrap();
+    settings.cert_path = dir.join("empty.pem");
+
+    let error = server_config(&settings, &Shutdown::new(Duration::from_secs(1)))
+        .expect_err("The certificate should be rejected");
+    assert!(error.to_string().contains("No certificate found"));
+}
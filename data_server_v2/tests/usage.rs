@@ -0,0 +1,159 @@
+use chrono::{DurationRound, SecondsFormat, TimeDelta, Utc};
+use reqwest::{header::CONTENT_TYPE, Client, Response};
+use rush_data_server::{database::usage::record_usage, model::usage::HourlyUsage};
+use serde_json::json;
+use std::{env, time::Duration};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+async fn spawn_metered_app() -> String {
+    env::set_var("APP__USAGE__AGGREGATION_INTERVAL_SECS", "1");
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    address
+}
+
+async fn get_usage(address: &str, path: &str, instance: Option<&str>) -> Response {
+    let now = Utc::now();
+    let from = (now - TimeDelta::hours(1)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let to = (now + TimeDelta::hours(1)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut query = vec![("from", from), ("to", to)];
+    query.extend(instance.map(|instance| ("instance", instance.to_owned())));
+    Client::new()
+        .get(format!("{address}{path}"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_instance(address: &str, instance: &str, api_key: Option<&str>) {
+    let mut request = Client::new()
+        .get(format!("{address}/"))
+        .header("Host", instance_host(instance));
+    if let Some(api_key) = api_key {
+        request = request.header("X-Api-Key", api_key);
+    }
+    let response = request.send().await.expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn requests_storage_and_api_keys_are_metered_hourly() {
+    let address = spawn_metered_app().await;
+    create_instance(&address, "acme").await;
+    create_instance(&address, "other").await;
+    publish_object(&address, "acme", "contact").await;
+    let response = Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .header("X-Api-Key", "first")
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    get_instance(&address, "acme", Some("first")).await;
+    get_instance(&address, "acme", Some("second")).await;
+    get_instance(&address, "acme", None).await;
+    get_instance(&address, "other", None).await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let response = get_usage(&address, "/usage", Some("acme")).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let usage: Vec<HourlyUsage> = response.json().await.unwrap();
+    assert!(usage.iter().all(|usage| usage.instance == "acme"));
+    // Publishing the object takes two requests.
+    assert_eq!(6, usage.iter().map(|usage| usage.api_requests).sum::<u64>());
+    let latest = usage.last().expect("No usage was recorded");
+    assert_eq!(1, latest.records);
+    assert!(latest.storage_bytes > 0);
+    assert_eq!(
+        2,
+        usage.iter().map(|usage| usage.active_users).max().unwrap()
+    );
+}
+
+#[actix_web::test]
+async fn requests_to_unknown_instances_are_not_metered() {
+    let address = spawn_metered_app().await;
+    Client::new()
+        .get(format!("{address}/"))
+        .header("Host", instance_host("ghost"))
+        .header("X-Api-Key", "random")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let response = get_usage(&address, "/usage", Some("ghost")).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let usage: Vec<HourlyUsage> = response.json().await.unwrap();
+    assert!(usage.is_empty(), "{usage:?}");
+}
+
+#[actix_web::test]
+async fn usage_is_exported_as_csv() {
+    let address = spawn_metered_app().await;
+    create_instance(&address, "acme").await;
+    get_instance(&address, "acme", None).await;
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let response = get_usage(&address, "/usage/export", None).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let csv = response.text().await.unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        Some("instance,hour,api_requests,records,storage_bytes,active_users"),
+        lines.next()
+    );
+    let row: Vec<_> = lines
+        .next()
+        .expect("No usage was exported")
+        .split(',')
+        .collect();
+    assert_eq!("acme", row[0]);
+    assert!(row[1].ends_with(":00:00Z"), "{}", row[1]);
+    assert_eq!("1", row[2]);
+}
+
+#[actix_web::test]
+async fn exported_fields_are_quoted_when_needed() {
+    let (address, db) = spawn_app().await.expect("Failed to spawn app.");
+    db.use_ns("root").use_db("root").await.unwrap();
+    let hour = Utc::now().duration_trunc(TimeDelta::hours(1)).unwrap();
+    record_usage(&db, "a,\"b\"", hour, 1, Vec::new(), None)
+        .await
+        .expect("Failed to record usage");
+
+    let response = get_usage(&address, "/usage/export", None).await;
+
+    let csv = response.text().await.unwrap();
+    let row = csv.lines().nth(1).expect("No usage was exported");
+    assert!(row.starts_with("\"a,\"\"b\"\"\","), "{row}");
+}
+
+#[actix_web::test]
+async fn empty_date_ranges_are_rejected() {
+    let address = spawn_metered_app().await;
+
+    let response = Client::new()
+        .get(format!("{address}/usage"))
+        .query(&[
+            ("from", "2024-01-02T00:00:00Z"),
+            ("to", "2024-01-01T00:00:00Z"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
@@ -1,28 +1,149 @@
-use actix_web::rt::spawn;
+// Each test binary uses a different subset of these helpers.
+#![allow(dead_code)]
+
+use actix_web::rt::{spawn, task::JoinHandle};
 use once_cell::sync::Lazy;
 use rush_data_server::{
     configuration::{get_configuration, Settings},
-    database::init_db,
+    database::{init_db, pool::ConnectionPool},
+    recycle_bin::spawn_purge,
+    shutdown::Shutdown,
     telemetry::init_telemetry,
 };
-use std::{env, io, net::TcpListener};
+use rustls::ServerConfig;
+use std::{env, io, net::TcpListener, time::Duration};
 use surrealdb::{engine::any::Any, Surreal};
 
 pub async fn spawn_app() -> io::Result<(String, Surreal<Any>)> {
+    let (address, _, db) = spawn_app_with_admin(false).await?;
+    Ok((address, db))
+}
+
+/// Spawns the app, optionally serving metrics on a separate admin address.
+pub async fn spawn_app_with_admin(
+    admin: bool,
+) -> io::Result<(String, Option<String>, Surreal<Any>)> {
+    let (address, admin_address, db, _) = spawn_server(admin, None).await?;
+    Ok((address, admin_address, db))
+}
+
+/// Spawns the app serving HTTPS, returning its port.
+pub async fn spawn_app_with_tls(tls: ServerConfig) -> io::Result<(u16, Surreal<Any>)> {
+    let (address, _, db, _) = spawn_server(false, Some(tls)).await?;
+    let port = address.rsplit(':').next().unwrap().parse().unwrap();
+    Ok((port, db))
+}
+
+/// Spawns the app, returning the handle that shuts it down and the task that
+/// completes once it has.
+pub async fn spawn_app_with_shutdown() -> io::Result<(String, Shutdown, JoinHandle<io::Result<()>>)>
+{
+    let (address, _, _, (shutdown, server)) = spawn_server(false, None).await?;
+    Ok((address, shutdown, server))
+}
+
+async fn spawn_server(
+    admin: bool,
+    tls: Option<ServerConfig>,
+) -> io::Result<(
+    String,
+    Option<String>,
+    Surreal<Any>,
+    (Shutdown, JoinHandle<io::Result<()>>),
+)> {
     env::set_var("APP_ENVIRONMENT", "test");
     Lazy::force(&TRACING);
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
     let port = listener.local_addr().unwrap().port();
-    let Settings { database, .. } = get_configuration().expect("Failed to read configuration.");
-    let db = init_db(database).await.expect("Could not initialize db");
-    let server = rush_data_server::run(listener, db.clone());
-    spawn(server);
+    let admin_listener =
+        admin.then(|| TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port"));
+    let admin_address = admin_listener
+        .as_ref()
+        .map(|listener| format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port()));
+    let Settings {
+        database,
+        application,
+        limits,
+        usage,
+        recycle_bin,
+        webhooks,
+        ..
+    } = get_configuration().expect("Failed to read configuration.");
+    let db = init_db(&database).await.expect("Could not initialize db");
+    let pool =
+        ConnectionPool::new(&database, db.clone()).expect("Could not create connection pool");
+    let shutdown = Shutdown::new(Duration::from_secs(application.shutdown_timeout_secs));
+    spawn_purge(pool.clone(), recycle_bin, shutdown.clone());
+    let server = rush_data_server::run(
+        listener,
+        admin_listener,
+        tls,
+        pool,
+        limits,
+        usage,
+        webhooks,
+        shutdown.clone(),
+    );
+    let server = spawn(server);
+
+    Ok((
+        format!("http://127.0.0.1:{}", port),
+        admin_address,
+        db,
+        (shutdown, server),
+    ))
+}
+
+/// The `Host` header that addresses requests to an instance.
+pub fn instance_host(name: &str) -> String {
+    format!("{name}.rush.test")
+}
+
+/// Creates and migrates an instance through the API.
+pub async fn create_instance(address: &str, name: &str) {
+    let response = reqwest::Client::new()
+        .post(format!("{address}/instance"))
+        .json(&serde_json::json!({ "name": name }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
+}
+
+/// Creates and publishes an object of an instance through the API, so that the
+/// resource API accepts its records.
+pub async fn publish_object(address: &str, instance: &str, object: &str) {
+    publish_object_with_settings(address, instance, object, serde_json::json!({})).await;
+}
+
+pub async fn publish_object_with_settings(
+    address: &str,
+    instance: &str,
+    object: &str,
+    settings: serde_json::Value,
+) {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{address}/schema/objects"))
+        .header("Host", instance_host(instance))
+        .json(&serde_json::json!({ "name": object, "settings": settings }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(201, response.status().as_u16());
 
-    Ok((format!("http://127.0.0.1:{}", port), db))
+    let response = client
+        .post(format!("{address}/schema/objects/{object}/publish"))
+        .header("Host", instance_host(instance))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(200, response.status().as_u16());
 }
 
 static TRACING: Lazy<io::Result<()>> = Lazy::new(|| {
-    init_telemetry()?;
+    let Settings { telemetry, .. } = get_configuration().expect("Failed to read configuration.");
+    init_telemetry(&telemetry)?;
 
     Ok(())
 });
@@ -0,0 +1,181 @@
+use reqwest::{Client, Response};
+use rush_data_server::model::version::RecordVersion;
+use serde_json::{json, Value};
+
+use crate::util::{
+    create_instance, instance_host, publish_object, publish_object_with_settings, spawn_app,
+};
+
+mod util;
+
+struct Contacts {
+    address: String,
+    client: Client,
+}
+
+impl Contacts {
+    async fn create(&self, content: Value) -> String {
+        let record: Value = self
+            .client
+            .post(format!("{}/api/contact", self.address))
+            .header("Host", instance_host("acme"))
+            .json(&content)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        record["id"].as_str().unwrap().to_owned()
+    }
+
+    async fn update(&self, id: &str, content: Value) {
+        let response = self
+            .client
+            .patch(format!("{}/api/contact/{id}", self.address))
+            .header("Host", instance_host("acme"))
+            .json(&content)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    async fn get(&self, path: &str) -> Response {
+        self.client
+            .get(format!("{}/api/contact/{path}", self.address))
+            .header("Host", instance_host("acme"))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    async fn versions(&self, id: &str) -> Vec<RecordVersion> {
+        let response = self.get(&format!("{id}/versions")).await;
+        assert_eq!(200, response.status().as_u16());
+        response.json().await.unwrap()
+    }
+}
+
+async fn contacts(settings: Option<Value>) -> Contacts {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+    match settings {
+        Some(settings) => publish_object_with_settings(&address, "acme", "contact", settings).await,
+        None => publish_object(&address, "acme", "contact").await,
+    }
+    Contacts {
+        address,
+        client: Client::new(),
+    }
+}
+
+#[actix_web::test]
+async fn every_write_adds_a_version_that_can_be_diffed_and_restored() {
+    let contacts = contacts(None).await;
+    let id = contacts.create(json!({ "name": "Ada" })).await;
+    contacts
+        .update(&id, json!({ "email": "ada@example.com" }))
+        .await;
+    contacts
+        .update(&id, json!({ "name": "Ada Lovelace" }))
+        .await;
+
+    let versions = contacts.versions(&id).await;
+    assert_eq!(
+        vec![3, 2, 1],
+        versions.iter().map(|v| v.version).collect::<Vec<_>>()
+    );
+    assert_eq!(Some(&json!("Ada")), versions[2].content.get("name"));
+    assert!(versions[0].content.get("id").is_none());
+    assert_eq!("anonymous", versions[0].actor);
+
+    let diff: Value = contacts
+        .get(&format!("{id}/versions/diff?from=1&to=3"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        json!({
+            "from": 1,
+            "to": 3,
+            "changes": {
+                "name": { "before": "Ada", "after": "Ada Lovelace" },
+                "email": { "before": null, "after": "ada@example.com" },
+            },
+        }),
+        diff
+    );
+
+    let response = contacts
+        .client
+        .post(format!(
+            "{}/api/contact/{id}/versions/1/restore",
+            contacts.address
+        ))
+        .header("Host", instance_host("acme"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let restored: Value = response.json().await.unwrap();
+    assert_eq!(json!({ "id": id, "name": "Ada" }), restored);
+    assert_eq!(4, contacts.versions(&id).await[0].version);
+}
+
+#[actix_web::test]
+async fn history_is_limited_by_the_object_settings() {
+    let contacts = contacts(Some(json!({ "history": { "max_versions": 2 } }))).await;
+    let id = contacts.create(json!({ "count": 0 })).await;
+    for count in 1..=3 {
+        contacts.update(&id, json!({ "count": count })).await;
+    }
+
+    let versions = contacts.versions(&id).await;
+
+    assert_eq!(
+        vec![4, 3],
+        versions.iter().map(|v| v.version).collect::<Vec<_>>()
+    );
+    assert_eq!(Some(&json!(3)), versions[0].content.get("count"));
+}
+
+#[actix_web::test]
+async fn no_history_is_kept_without_versions() {
+    let contacts = contacts(Some(json!({ "history": { "max_versions": 0 } }))).await;
+    let id = contacts.create(json!({ "count": 0 })).await;
+    contacts.update(&id, json!({ "count": 1 })).await;
+
+    assert!(contacts.versions(&id).await.is_empty());
+}
+
+#[actix_web::test]
+async fn missing_versions_return_404() {
+    let contacts = contacts(None).await;
+    let id = contacts.create(json!({ "name": "Ada" })).await;
+
+    let response = contacts
+        .get(&format!("{id}/versions/diff?from=1&to=2"))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = contacts.get("missing/versions").await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn invalid_history_settings_are_rejected() {
+    let (address, _) = spawn_app().await.expect("Failed to spawn app.");
+    create_instance(&address, "acme").await;
+
+    let response = Client::new()
+        .post(format!("{address}/schema/objects"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "contact", "settings": { "history": { "max_versions": -1 } } }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
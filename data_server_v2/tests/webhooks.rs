@@ -0,0 +1,599 @@
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use rush_data_server::model::webhook::{
+    CreatedWebhook, DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent,
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    collections::VecDeque,
+    env,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use surrealdb::{engine::any::Any, Surreal};
+
+use crate::util::{create_instance, instance_host, publish_object, spawn_app};
+
+mod util;
+
+/// A request received by the stand-in receiver.
+#[derive(Debug, Clone)]
+struct Received {
+    event: String,
+    delivery: String,
+    timestamp: String,
+    signature: String,
+    body: String,
+}
+
+/// A stand-in for the endpoint of a webhook, which records what it receives and
+/// responds with the queued statuses, then with 200 OK, after `delay`.
+#[derive(Clone, Default)]
+struct Receiver {
+    received: Arc<Mutex<Vec<Received>>>,
+    statuses: Arc<Mutex<VecDeque<u16>>>,
+    delay: Duration,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl Receiver {
+    fn spawn(statuses: &[u16]) -> (String, Self) {
+        Self::spawn_with_delay(statuses, Duration::ZERO)
+    }
+
+    fn spawn_with_delay(statuses: &[u16], delay: Duration) -> (String, Self) {
+        let receiver = Self {
+            delay,
+            ..Self::default()
+        };
+        receiver.statuses.lock().unwrap().extend(statuses);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+        let port = listener.local_addr().unwrap().port();
+        let state = receiver.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(state.clone()))
+                .route("/hook", web::post().to(receive))
+        })
+        .listen(listener)
+        .expect("Failed to listen")
+        .run();
+        actix_web::rt::spawn(server);
+        (format!("http://127.0.0.1:{port}/hook"), receiver)
+    }
+
+    fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn receive(req: HttpRequest, body: String, receiver: web::Data<Receiver>) -> HttpResponse {
+    let in_flight = receiver.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    receiver
+        .max_in_flight
+        .fetch_max(in_flight, Ordering::SeqCst);
+    tokio::time::sleep(receiver.delay).await;
+    receiver.in_flight.fetch_sub(1, Ordering::SeqCst);
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    receiver.received.lock().unwrap().push(Received {
+        event: header("X-Rush-Event"),
+        delivery: header("X-Rush-Delivery"),
+        timestamp: header("X-Rush-Timestamp"),
+        signature: header("X-Rush-Signature"),
+        body,
+    });
+    let status = receiver.statuses.lock().unwrap().pop_front().unwrap_or(200);
+    HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap()).finish()
+}
+
+fn expected_signature(secret: &str, timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn spawn_app_with_fast_webhooks() -> (String, Surreal<Any>) {
+    env::set_var("APP__WEBHOOKS__DISPATCH_INTERVAL_SECS", "1");
+    env::set_var("APP__WEBHOOKS__RETRY__MAX_ATTEMPTS", "3");
+    env::set_var("APP__WEBHOOKS__RETRY__INITIAL_BACKOFF_MS", "100");
+    env::set_var("APP__WEBHOOKS__RETRY__MAX_BACKOFF_MS", "200");
+    spawn_app().await.expect("Failed to spawn app.")
+}
+
+async fn create_webhook(address: &str, instance: &str, webhook: Value) -> CreatedWebhook {
+    let response = Client::new()
+        .post(format!("{address}/webhooks"))
+        .header("Host", instance_host(instance))
+        .json(&webhook)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(StatusCode::CREATED, response.status());
+    response.json().await.unwrap()
+}
+
+async fn list_deliveries(address: &str, instance: &str, webhook: &str) -> Vec<WebhookDelivery> {
+    Client::new()
+        .get(format!("{address}/webhooks/{webhook}/deliveries"))
+        .header("Host", instance_host(instance))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Waits for the receiver to get `count` requests.
+async fn wait_for_requests(receiver: &Receiver, count: usize) -> Vec<Received> {
+    for _ in 0..100 {
+        let received = receiver.received();
+        if received.len() >= count {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "Received {:?}, expected {count} requests",
+        receiver.received()
+    );
+}
+
+/// Waits for every delivery of a webhook to be settled.
+async fn wait_for_deliveries(address: &str, instance: &str, webhook: &str) -> Vec<WebhookDelivery> {
+    for _ in 0..100 {
+        let deliveries = list_deliveries(address, instance, webhook).await;
+        if !deliveries.is_empty()
+            && deliveries
+                .iter()
+                .all(|delivery| delivery.status != DeliveryStatus::Pending)
+        {
+            return deliveries;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The deliveries of webhook `{webhook}` were not settled");
+}
+
+#[actix_web::test]
+async fn record_events_are_delivered_signed() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    publish_object(&address, "acme", "deal").await;
+    let (url, receiver) = Receiver::spawn(&[]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({
+            "url": url,
+            "events": ["record_created", "record_updated", "record_deleted"],
+            "object": "contact",
+        }),
+    )
+    .await;
+    assert_eq!(64, webhook.secret.len());
+
+    let client = Client::new();
+    let host = instance_host("acme");
+    let record: Value = client
+        .post(format!("{address}/api/contact"))
+        .header("Host", &host)
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = record["id"].as_str().unwrap();
+    client
+        .patch(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .json(&json!({ "name": "Ada Lovelace" }))
+        .send()
+        .await
+        .unwrap();
+    client
+        .delete(format!("{address}/api/contact/{id}"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(format!("{address}/api/deal"))
+        .header("Host", &host)
+        .json(&json!({ "amount": 100 }))
+        .send()
+        .await
+        .unwrap();
+
+    let received = wait_for_requests(&receiver, 3).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(
+        3,
+        receiver.received().len(),
+        "Only contact events are delivered"
+    );
+
+    let events: Vec<_> = received.iter().map(|r| r.event.as_str()).collect();
+    assert_eq!(
+        vec!["record_created", "record_updated", "record_deleted"],
+        events
+    );
+    for request in &received {
+        assert_eq!(
+            expected_signature(&webhook.secret, &request.timestamp, &request.body),
+            request.signature
+        );
+        let payload: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(request.delivery, payload["id"]);
+        assert_eq!(request.event, payload["event"]);
+        assert_eq!("acme", payload["instance"]);
+        assert_eq!("contact", payload["object"]);
+        assert_eq!(id, payload["data"]["record"]["id"]);
+    }
+    let updated: Value = serde_json::from_str(&received[1].body).unwrap();
+    assert_eq!("Ada Lovelace", updated["data"]["record"]["name"]);
+    assert_eq!("Ada", updated["data"]["previous"]["name"]);
+
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    assert_eq!(3, deliveries.len());
+    for delivery in deliveries {
+        assert_eq!(DeliveryStatus::Delivered, delivery.status);
+        assert_eq!(1, delivery.attempts.len());
+        assert_eq!(Some(200), delivery.attempts[0].status_code);
+        assert_eq!(None, delivery.next_attempt_at);
+    }
+}
+
+#[actix_web::test]
+async fn webhook_secrets_are_stored_encrypted() {
+    let (address, db) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": "http://127.0.0.1:1/hook", "events": ["record_created"] }),
+    )
+    .await;
+
+    db.invalidate().await.unwrap();
+    db.use_ns("acme").use_db("acme").await.unwrap();
+    let stored: Option<String> = db
+        .query("SELECT VALUE secret FROM type::thing('webhook', $id)")
+        .bind(("id", &webhook.webhook.id))
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    let stored = stored.expect("The webhook is stored");
+    assert!(!stored.contains(&webhook.secret));
+}
+
+#[actix_web::test]
+async fn schema_publishes_are_delivered() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    let (url, receiver) = Receiver::spawn(&[]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["object_published"] }),
+    )
+    .await;
+
+    publish_object(&address, "acme", "contact").await;
+
+    let received = wait_for_requests(&receiver, 1).await;
+    assert_eq!("object_published", received[0].event);
+    let payload: Value = serde_json::from_str(&received[0].body).unwrap();
+    assert_eq!("contact", payload["object"]);
+    assert_eq!("contact", payload["data"]["object"]["name"]);
+    assert_eq!(true, payload["data"]["object"]["published"]);
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    assert_eq!(WebhookEvent::ObjectPublished, deliveries[0].event);
+}
+
+#[actix_web::test]
+async fn due_deliveries_are_sent_concurrently() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let (url, receiver) = Receiver::spawn_with_delay(&[], Duration::from_millis(500));
+    create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["record_created"] }),
+    )
+    .await;
+
+    let client = Client::new();
+    for name in ["Ada", "Grace", "Edsger", "Barbara", "Alan"] {
+        client
+            .post(format!("{address}/api/contact"))
+            .header("Host", instance_host("acme"))
+            .json(&json!({ "name": name }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    wait_for_requests(&receiver, 5).await;
+    assert!(receiver.max_in_flight.load(Ordering::SeqCst) > 1);
+}
+
+#[actix_web::test]
+async fn deliveries_claimed_by_another_server_wait_for_the_claim_to_run_out() {
+    let (address, db) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    let (url, receiver) = Receiver::spawn(&[]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["record_created"] }),
+    )
+    .await;
+
+    db.invalidate().await.unwrap();
+    db.use_ns("acme").use_db("acme").await.unwrap();
+    let delivery: Option<String> = db
+        .query(
+            "CREATE webhook_delivery CONTENT {
+                webhook: type::thing('webhook', $webhook),
+                event: 'record_created',
+                object: 'contact',
+                claimed_by: 'another-server',
+                claimed_until: time::now() + 1h,
+            } RETURN VALUE meta::id(id)",
+        )
+        .bind(("webhook", &webhook.webhook.id))
+        .await
+        .unwrap()
+        .take(0)
+        .unwrap();
+    let delivery = delivery.expect("The delivery is created");
+
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert!(receiver.received().is_empty());
+
+    db.invalidate().await.unwrap();
+    db.use_ns("acme").use_db("acme").await.unwrap();
+    db.query("UPDATE type::thing('webhook_delivery', $id) SET claimed_until = time::now()")
+        .bind(("id", &delivery))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    let received = wait_for_requests(&receiver, 1).await;
+    assert_eq!(delivery, received[0].delivery);
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    assert_eq!(DeliveryStatus::Delivered, deliveries[0].status);
+}
+
+#[actix_web::test]
+async fn failed_deliveries_are_retried_with_backoff() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let (url, receiver) = Receiver::spawn(&[500, 503]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["record_created"] }),
+    )
+    .await;
+
+    Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    let delivery = &deliveries[0];
+    assert_eq!(DeliveryStatus::Delivered, delivery.status);
+    let statuses: Vec<_> = delivery.attempts.iter().map(|a| a.status_code).collect();
+    assert_eq!(vec![Some(500), Some(503), Some(200)], statuses);
+    assert!(delivery.attempts[0].error.is_some());
+    assert!(delivery.attempts[1].at < delivery.attempts[2].at);
+
+    let received = receiver.received();
+    assert_eq!(3, received.len());
+    assert!(
+        received.iter().all(|r| r.delivery == delivery.id),
+        "Retries are the same delivery"
+    );
+}
+
+#[actix_web::test]
+async fn deliveries_are_given_up_after_the_configured_attempts() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let (url, receiver) = Receiver::spawn(&[500, 500, 500, 500]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["record_created"] }),
+    )
+    .await;
+
+    Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    assert_eq!(DeliveryStatus::Failed, deliveries[0].status);
+    assert_eq!(3, deliveries[0].attempts.len());
+    assert_eq!(None, deliveries[0].next_attempt_at);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(3, receiver.received().len());
+}
+
+#[actix_web::test]
+async fn webhooks_can_be_listed_and_deleted() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    let client = Client::new();
+    let host = instance_host("acme");
+    for webhook in [
+        json!({ "url": "not a url", "events": ["record_created"] }),
+        json!({ "url": "ftp://example.com/hook", "events": ["record_created"] }),
+        json!({ "url": "https://example.com/hook", "events": [] }),
+        json!({ "url": "https://example.com/hook", "events": ["record_exploded"] }),
+        json!({ "url": "https://example.com/hook", "events": ["record_created"], "object": "Bad Name" }),
+    ] {
+        let response = client
+            .post(format!("{address}/webhooks"))
+            .header("Host", &host)
+            .json(&webhook)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status(), "{webhook}");
+    }
+
+    let created = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": "https://example.com/hook", "events": ["record_created"] }),
+    )
+    .await;
+    let webhooks: Vec<Value> = client
+        .get(format!("{address}/webhooks"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, webhooks.len());
+    assert!(
+        webhooks[0].get("secret").is_none(),
+        "Secrets are not listed"
+    );
+    let listed: Webhook = serde_json::from_value(webhooks[0].clone()).unwrap();
+    assert_eq!(created.webhook, listed);
+
+    let url = format!("{address}/webhooks/{}", created.webhook.id);
+    let response = client
+        .delete(&url)
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+    let response = client
+        .delete(&url)
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    let response = client
+        .get(format!("{url}/deliveries"))
+        .header("Host", &host)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+}
+
+#[actix_web::test]
+async fn webhooks_cannot_target_private_addresses() {
+    let (address, _) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    let client = Client::new();
+    for url in [
+        "http://localhost:8080/hook",
+        "http://10.0.0.1/hook",
+        "http://192.168.1.1/hook",
+        "http://169.254.169.254/latest/meta-data",
+        "http://0.0.0.0/hook",
+        "http://[::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "http://[fd00::1]/hook",
+    ] {
+        let response = client
+            .post(format!("{address}/webhooks"))
+            .header("Host", instance_host("acme"))
+            .json(&json!({ "url": url, "events": ["record_created"] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status(), "{url}");
+        let body: Value = response.json().await.unwrap();
+        assert!(
+            body["message"]
+                .as_str()
+                .unwrap()
+                .contains("not a public address"),
+            "{body}"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn deliveries_check_where_their_host_resolves_now() {
+    let (address, db) = spawn_app_with_fast_webhooks().await;
+    create_instance(&address, "acme").await;
+    publish_object(&address, "acme", "contact").await;
+    let (url, receiver) = Receiver::spawn(&[]);
+    let webhook = create_webhook(
+        &address,
+        "acme",
+        json!({ "url": url, "events": ["record_created"] }),
+    )
+    .await;
+
+    // As if the host of the webhook had been pointed at a private address since
+    // it was registered. Only `127.0.0.1` is allowed in tests.
+    db.invalidate().await.unwrap();
+    db.use_ns("acme").use_db("acme").await.unwrap();
+    db.query("UPDATE type::thing('webhook', $id) SET url = $url")
+        .bind(("id", &webhook.webhook.id))
+        .bind(("url", url.replace("127.0.0.1", "localhost")))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    Client::new()
+        .post(format!("{address}/api/contact"))
+        .header("Host", instance_host("acme"))
+        .json(&json!({ "name": "Ada" }))
+        .send()
+        .await
+        .unwrap();
+
+    let deliveries = wait_for_deliveries(&address, "acme", &webhook.webhook.id).await;
+    assert_eq!(DeliveryStatus::Failed, deliveries[0].status);
+    let error = deliveries[0].attempts[0]
+        .error
+        .as_deref()
+        .unwrap_or_default();
+    assert!(error.contains("not a public address"), "{error}");
+    assert!(receiver.received().is_empty());
+}